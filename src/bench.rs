@@ -7,6 +7,7 @@
 use std::borrow::ToOwned;
 use std::collections::hash_map::{Entry, HashMap};
 
+use byteset::ByteSet;
 use tendril::StrTendril;
 
 fn index_words_string(input: &String) -> HashMap<char, Vec<String>> {
@@ -48,6 +49,36 @@ fn index_words_tendril(input: &StrTendril) -> HashMap<char, Vec<StrTendril>> {
     }
 }
 
+fn is_text(c: char) -> bool {
+    !matches!(c, '<' | '&' | '\r' | '\0')
+}
+
+fn text_len_char_run(input: &StrTendril) -> usize {
+    let mut t = input.clone();
+    let mut len = 0;
+    while let Some((run, text)) = t.pop_front_char_run(is_text) {
+        if text {
+            len += run.len();
+        }
+    }
+    len
+}
+
+fn text_len_byte_set(input: &StrTendril, text: &ByteSet) -> usize {
+    let mut t = input.clone();
+    let mut len = 0;
+    loop {
+        if let Some(run) = t.pop_front_byte_run(text) {
+            len += run.len();
+        }
+        // Next is a byte of markup.
+        if t.is_empty() {
+            return len;
+        }
+        t.pop_front(1);
+    }
+}
+
 static EN_1: &'static str = "Days turn to nights turn to paper into rocks into plastic";
 
 static EN_2: &'static str =
@@ -157,3 +188,54 @@ mod index_words {
     bench!(KR_1);
     bench!(HTML_KR_1);
 }
+
+mod text_len {
+    macro_rules! bench {
+        ($txt:ident) => {
+            #[allow(non_snake_case)]
+            mod $txt {
+                use byteset::ByteSet;
+                use tendril::StrTendril;
+
+                const SIZE: usize = 65536;
+
+                fn input() -> StrTendril {
+                    let markup = ::tendril::bench::$txt.replace(". ", ".<br>\r\n");
+                    let mut t = StrTendril::new();
+                    while t.len() < SIZE {
+                        t.push_slice(&markup);
+                    }
+                    t
+                }
+
+                #[bench]
+                fn char_run(b: &mut ::test::Bencher) {
+                    let t = input();
+                    b.iter(|| ::tendril::bench::text_len_char_run(&t));
+                }
+
+                #[bench]
+                fn byte_set(b: &mut ::test::Bencher) {
+                    let t = input();
+                    let text = ByteSet::from_bytes(b"<&\r\0").complement();
+                    b.iter(|| ::tendril::bench::text_len_byte_set(&t, &text));
+                }
+
+                #[test]
+                fn correctness() {
+                    use tendril::bench::{is_text, text_len_byte_set, text_len_char_run};
+
+                    let t = input();
+                    let expected: usize =
+                        t.chars().filter(|&c| is_text(c)).map(char::len_utf8).sum();
+                    let text = ByteSet::from_bytes(b"<&\r\0").complement();
+                    assert_eq!(expected, text_len_char_run(&t));
+                    assert_eq!(expected, text_len_byte_set(&t, &text));
+                }
+            }
+        };
+    }
+
+    bench!(EN_2);
+    bench!(HTML_KR_1);
+}
@@ -6,7 +6,8 @@
 
 //! Provides an unsafe owned buffer type, used in implementing `Tendril`.
 
-use std::{mem, ptr, slice, u32};
+use alloc::vec::Vec;
+use core::{cmp, mem, ptr, slice, u32};
 
 use OFLOW;
 
@@ -23,7 +24,7 @@ pub struct Buf32<H> {
 }
 
 #[inline(always)]
-fn bytes_to_vec_capacity<H>(x: u32) -> usize {
+pub fn bytes_to_vec_capacity<H>(x: u32) -> usize {
     let header = mem::size_of::<H>();
     debug_assert!(header > 0);
     let x = (x as usize).checked_add(header).expect(OFLOW);
@@ -90,12 +91,34 @@ impl<H> Buf32<H> {
         self.cap = new_cap;
         mem::forget(vec);
     }
+
+    /// Shrink the capacity to `new_cap`, but no lower than `MIN_CAP`.
+    ///
+    /// The current length must fit within `new_cap`.
+    #[inline]
+    pub unsafe fn shrink(&mut self, new_cap: u32) {
+        let new_cap = cmp::max(new_cap, MIN_CAP);
+        debug_assert!(self.len <= new_cap);
+        if new_cap >= self.cap {
+            return;
+        }
+
+        // `shrink_to_fit` shrinks to the length, so claim the units we keep.
+        let mut vec = Vec::from_raw_parts(self.ptr, 0, bytes_to_vec_capacity::<H>(self.cap));
+        vec.set_len(bytes_to_vec_capacity::<H>(new_cap));
+        vec.shrink_to_fit();
+        vec.set_len(0);
+        self.ptr = vec.as_mut_ptr();
+        let bytes = (vec.capacity() - 1) * mem::size_of::<H>();
+        self.cap = cmp::min(bytes, MAX_LEN) as u32;
+        mem::forget(vec);
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::Buf32;
-    use std::ptr;
+    use core::ptr;
 
     #[test]
     fn smoke_test() {
@@ -114,6 +137,10 @@ mod test {
             assert!(b.cap >= 1337);
             assert_eq!(b"Hello", b.data());
 
+            b.shrink(5);
+            assert_eq!(super::MIN_CAP, b.cap);
+            assert_eq!(b"Hello", b.data());
+
             b.destroy();
         }
     }
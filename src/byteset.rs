@@ -0,0 +1,280 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Precomputed byte classes, for scanning runs of bytes in bulk.
+//!
+//! With SSSE3 on x86-64, sixteen bytes are classified at once by looking
+//! up both nibbles of each byte with `pshufb`. Elsewhere the table is
+//! consulted a byte at a time. SSSE3 is detected at run time when `std`
+//! is available.
+
+use core::{cmp, fmt};
+
+/// A set of bytes, such as a character class in a tokenizer.
+///
+/// Build it once and pass it to `Tendril::pop_front_byte_run` or
+/// `Tendril::pop_front_while_ascii`.
+///
+/// ```
+/// use tendril::{ByteSet, StrTendril};
+///
+/// let special = ByteSet::from_bytes(b"<&\r\0").complement();
+/// let mut t = StrTendril::from_slice("some t\u{e9}xt<b>");
+/// assert_eq!(&*t.pop_front_byte_run(&special).unwrap(), "some t\u{e9}xt");
+/// assert_eq!(&*t, "<b>");
+/// ```
+#[derive(Copy, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ByteSet {
+    // Bit `n` of `rows[i]` is set iff the byte `n << 4 | i` is in the set,
+    // and likewise `rows[16 + i]` for the bytes from 0x80. This is the
+    // layout the SIMD lookup wants.
+    rows: [u8; 32],
+}
+
+impl ByteSet {
+    /// Create an empty set.
+    #[inline]
+    pub fn new() -> ByteSet {
+        ByteSet { rows: [0; 32] }
+    }
+
+    /// Create a set holding the given bytes.
+    pub fn from_bytes(bytes: &[u8]) -> ByteSet {
+        let mut set = ByteSet::new();
+        for &b in bytes {
+            set.insert(b);
+        }
+        set
+    }
+
+    /// Create a set holding every byte for which `pred` returns `true`.
+    pub fn from_fn<P>(mut pred: P) -> ByteSet
+    where
+        P: FnMut(u8) -> bool,
+    {
+        let mut set = ByteSet::new();
+        for b in 0..=0xFF_u8 {
+            if pred(b) {
+                set.insert(b);
+            }
+        }
+        set
+    }
+
+    /// Add a byte to the set.
+    #[inline]
+    pub fn insert(&mut self, b: u8) {
+        self.rows[row(b)] |= bit(b);
+    }
+
+    /// Is the byte in the set?
+    #[inline]
+    pub fn contains(&self, b: u8) -> bool {
+        contains(&self.rows, b)
+    }
+
+    /// Get the set of all bytes which are not in this one.
+    pub fn complement(&self) -> ByteSet {
+        let mut set = *self;
+        for x in set.rows.iter_mut() {
+            *x = !*x;
+        }
+        set
+    }
+
+    /// Get the length of the prefix of `buf` whose bytes are all in the set.
+    #[inline]
+    pub fn span(&self, buf: &[u8]) -> usize {
+        span(&self.rows, buf)
+    }
+
+    /// Does the set hold either all of the bytes from 0x80 up, or none?
+    #[inline]
+    pub(crate) fn is_uniform_above_ascii(&self) -> bool {
+        let high = &self.rows[16..];
+        high.iter().all(|&x| x == 0) || high.iter().all(|&x| x == 0xFF)
+    }
+
+    /// Get the length of the prefix of `buf` whose bytes are all ASCII and
+    /// in the set. Bytes from 0x80 are never matched.
+    #[inline]
+    pub fn ascii_span(&self, buf: &[u8]) -> usize {
+        let mut rows = [0; 32];
+        rows[..16].copy_from_slice(&self.rows[..16]);
+        span(&rows, buf)
+    }
+}
+
+impl fmt::Debug for ByteSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set()
+            .entries((0..=0xFF_u8).filter(|&b| self.contains(b)))
+            .finish()
+    }
+}
+
+#[inline(always)]
+fn row(b: u8) -> usize {
+    ((b >> 3) & 0x10 | b & 0xF) as usize
+}
+
+#[inline(always)]
+fn bit(b: u8) -> u8 {
+    1 << ((b >> 4) & 7)
+}
+
+#[inline(always)]
+fn contains(rows: &[u8; 32], b: u8) -> bool {
+    rows[row(b)] & bit(b) != 0
+}
+
+/// Runs in tokenizers are often short, and not worth the setup of a SIMD
+/// loop. So the first 16 bytes are looked up one at a time.
+#[inline]
+fn span(rows: &[u8; 32], buf: &[u8]) -> usize {
+    let head = cmp::min(buf.len(), 16);
+    match span_bytes(rows, &buf[..head]) {
+        16 => 16 + imp::span(rows, &buf[16..]),
+        n => n,
+    }
+}
+
+#[inline]
+fn span_bytes(rows: &[u8; 32], buf: &[u8]) -> usize {
+    buf.iter()
+        .position(|&b| !contains(rows, b))
+        .unwrap_or(buf.len())
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use core::arch::x86_64::*;
+
+    #[inline]
+    pub fn span(rows: &[u8; 32], buf: &[u8]) -> usize {
+        if buf.len() >= 16 && has_ssse3() {
+            unsafe { ssse3(rows, buf) }
+        } else {
+            super::span_bytes(rows, buf)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_ssse3() -> bool {
+        is_x86_feature_detected!("ssse3")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_ssse3() -> bool {
+        cfg!(target_feature = "ssse3")
+    }
+
+    #[target_feature(enable = "ssse3")]
+    unsafe fn ssse3(rows: &[u8; 32], buf: &[u8]) -> usize {
+        let lo_rows = _mm_loadu_si128(rows.as_ptr() as *const __m128i);
+        let hi_rows = _mm_loadu_si128(rows.as_ptr().add(16) as *const __m128i);
+        let bits = _mm_setr_epi8(1, 2, 4, 8, 16, 32, 64, -128, 1, 2, 4, 8, 16, 32, 64, -128);
+        let low_nibble = _mm_set1_epi8(0x0F);
+        let index_bits = _mm_set1_epi8(0x8F_u8 as i8);
+        let high_bit = _mm_set1_epi8(-128);
+
+        let p = buf.as_ptr();
+        let mut i = 0;
+        while i + 16 <= buf.len() {
+            let v = _mm_loadu_si128(p.add(i) as *const __m128i);
+            // `pshufb` gives zero for an index with the high bit set, so
+            // each lookup only sees the bytes from its own half.
+            let index = _mm_and_si128(v, index_bits);
+            let row = _mm_or_si128(
+                _mm_shuffle_epi8(lo_rows, index),
+                _mm_shuffle_epi8(hi_rows, _mm_xor_si128(index, high_bit)),
+            );
+            let bit = _mm_shuffle_epi8(bits, _mm_and_si128(_mm_srli_epi16(v, 4), low_nibble));
+            let hit = _mm_cmpeq_epi8(_mm_and_si128(row, bit), bit);
+            let miss = !_mm_movemask_epi8(hit) & 0xFFFF;
+            if miss != 0 {
+                return i + miss.trailing_zeros() as usize;
+            }
+            i += 16;
+        }
+        i + super::span_bytes(rows, &buf[i..])
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod imp {
+    #[inline]
+    pub fn span(rows: &[u8; 32], buf: &[u8]) -> usize {
+        super::span_bytes(rows, buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{span_bytes, ByteSet};
+    use alloc::{format, vec, vec::Vec};
+    use rand::{self, Rng};
+
+    #[test]
+    fn membership() {
+        let set = ByteSet::from_bytes(b"<&\r\0\x80\xff");
+        for b in 0..=0xFF_u8 {
+            assert_eq!(b"<&\r\0\x80\xff".contains(&b), set.contains(b));
+            assert_eq!(!set.contains(b), set.complement().contains(b));
+        }
+        assert_eq!(set, ByteSet::from_fn(|b| b"<&\r\0\x80\xff".contains(&b)));
+        assert_eq!(ByteSet::new(), ByteSet::from_bytes(b""));
+        assert_eq!("{0, 13, 38, 60, 128, 255}", format!("{:?}", set));
+    }
+
+    #[test]
+    fn span() {
+        let set = ByteSet::from_fn(|b| b.is_ascii_alphanumeric() || b >= 0xC0);
+        let mut buf = vec![b'a'; 100];
+        for len in 0..buf.len() {
+            assert_eq!(len, set.span(&buf[..len]));
+            assert_eq!(len, set.ascii_span(&buf[..len]));
+        }
+        for pos in 0..buf.len() {
+            for &(b, in_set) in &[(b' ', false), (0x80, false), (0xC3, true)] {
+                buf[pos] = b;
+                for start in 0..4 {
+                    let end = if in_set || pos < start {
+                        buf.len()
+                    } else {
+                        pos
+                    };
+                    assert_eq!(end - start, set.span(&buf[start..]));
+                    let end = if pos < start { buf.len() } else { pos };
+                    assert_eq!(end - start, set.ascii_span(&buf[start..]));
+                }
+            }
+            buf[pos] = b'a';
+        }
+    }
+
+    #[test]
+    fn span_random() {
+        let mut rng = rand::thread_rng();
+        for _ in 0..500 {
+            let members: Vec<u8> = (0..rng.gen_range(0, 200)).map(|_| rng.gen()).collect();
+            let set = ByteSet::from_bytes(&members);
+            let mut buf = vec![];
+            for _ in 0..rng.gen_range(0, 100) {
+                buf.push(*rng.choose(&members).unwrap_or(&0));
+            }
+            for _ in 0..20 {
+                buf.push(rng.gen());
+            }
+            let mut ascii = set.rows;
+            ascii[16..].copy_from_slice(&[0; 16]);
+            assert_eq!(span_bytes(&set.rows, &buf), set.span(&buf));
+            assert_eq!(span_bytes(&ascii, &buf), set.ascii_span(&buf));
+        }
+    }
+}
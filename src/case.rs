@@ -0,0 +1,212 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Case conversion.
+//!
+//! Each conversion returns a clone of the input, sharing its buffer, when
+//! the text is already in the target case.
+
+use fmt;
+use tendril::{Atomicity, Tendril};
+
+macro_rules! ascii_case_methods {
+    ($($fmt:ident)*) => {$(
+        impl<A> Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            /// Make a copy with ASCII letters in lower case.
+            #[inline]
+            pub fn to_ascii_lowercase(&self) -> Tendril<fmt::$fmt, A> {
+                let mut t = self.clone();
+                t.make_ascii_lowercase();
+                t
+            }
+
+            /// Make a copy with ASCII letters in upper case.
+            #[inline]
+            pub fn to_ascii_uppercase(&self) -> Tendril<fmt::$fmt, A> {
+                let mut t = self.clone();
+                t.make_ascii_uppercase();
+                t
+            }
+
+            /// Convert ASCII letters to lower case in place.
+            ///
+            /// A shared buffer is only copied if something changes.
+            #[inline]
+            pub fn make_ascii_lowercase(&mut self) {
+                if self.as_bytes().iter().any(u8::is_ascii_uppercase) {
+                    self.as_mut_byte_slice().make_ascii_lowercase();
+                }
+            }
+
+            /// Convert ASCII letters to upper case in place.
+            ///
+            /// A shared buffer is only copied if something changes.
+            #[inline]
+            pub fn make_ascii_uppercase(&mut self) {
+                if self.as_bytes().iter().any(u8::is_ascii_lowercase) {
+                    self.as_mut_byte_slice().make_ascii_uppercase();
+                }
+            }
+
+            /// Compare, ignoring the case of ASCII letters.
+            #[inline]
+            pub fn eq_ignore_ascii_case(&self, other: &Self) -> bool {
+                self.as_bytes().eq_ignore_ascii_case(other.as_bytes())
+            }
+        }
+    )*};
+}
+
+ascii_case_methods!(UTF8 ASCII Latin1);
+
+/// Re-encode each character through `map`, or return `None` if one of the
+/// results can't be represented. Shares the buffer if nothing changes.
+fn map_chars<F, A, M, I>(t: &Tendril<F, A>, map: M) -> Option<Tendril<F, A>>
+where
+    F: for<'a> fmt::CharFormat<'a>,
+    A: Atomicity,
+    M: Fn(char) -> I,
+    I: Iterator<Item = char>,
+{
+    let unchanged = unsafe { F::char_indices(t.as_bytes()) }.all(|(_, c)| {
+        let mut mapped = map(c);
+        mapped.next() == Some(c) && mapped.next().is_none()
+    });
+    if unchanged {
+        return Some(t.clone());
+    }
+
+    let mut out = Tendril::<F, A>::new();
+    for (_, c) in unsafe { F::char_indices(t.as_bytes()) } {
+        for m in map(c) {
+            out.try_push_char(m).ok()?;
+        }
+    }
+    Some(out)
+}
+
+impl<A> Tendril<fmt::UTF8, A>
+where
+    A: Atomicity,
+{
+    /// Make a copy in lower case, as by `str::to_lowercase`.
+    #[inline]
+    pub fn to_lowercase(&self) -> Tendril<fmt::UTF8, A> {
+        if self.chars().all(|c| c.to_lowercase().eq(Some(c))) {
+            return self.clone();
+        }
+        // Lowercasing depends on context for final sigma.
+        Tendril::from(self.as_ref().to_lowercase())
+    }
+
+    /// Make a copy in upper case, as by `str::to_uppercase`.
+    #[inline]
+    pub fn to_uppercase(&self) -> Tendril<fmt::UTF8, A> {
+        map_chars(self, char::to_uppercase).unwrap()
+    }
+}
+
+impl<A> Tendril<fmt::ASCII, A>
+where
+    A: Atomicity,
+{
+    /// Make a copy in lower case. This is the same as `to_ascii_lowercase`.
+    #[inline]
+    pub fn to_lowercase(&self) -> Tendril<fmt::ASCII, A> {
+        self.to_ascii_lowercase()
+    }
+
+    /// Make a copy in upper case. This is the same as `to_ascii_uppercase`.
+    #[inline]
+    pub fn to_uppercase(&self) -> Tendril<fmt::ASCII, A> {
+        self.to_ascii_uppercase()
+    }
+}
+
+impl<A> Tendril<fmt::Latin1, A>
+where
+    A: Atomicity,
+{
+    /// Make a copy in lower case.
+    ///
+    /// Every upper case letter in Latin-1 has its lower case in Latin-1
+    /// too, so this can't fail.
+    #[inline]
+    pub fn to_lowercase(&self) -> Tendril<fmt::Latin1, A> {
+        map_chars(self, char::to_lowercase).unwrap()
+    }
+
+    /// Make a copy in upper case, if it can be represented.
+    ///
+    /// Returns `None` if the text contains `ÿ` or `µ`, whose upper case
+    /// forms aren't in Latin-1.
+    #[inline]
+    pub fn try_to_uppercase(&self) -> Option<Tendril<fmt::Latin1, A>> {
+        map_chars(self, char::to_uppercase)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fmt;
+    use tendril::{SliceExt, Tendril};
+
+    fn latin1(x: &[u8]) -> Tendril<fmt::Latin1> {
+        x.to_tendril().try_reinterpret().unwrap()
+    }
+
+    #[test]
+    fn ascii_case() {
+        let t = "already lower case, with ünïcödé".to_tendril();
+        let l = t.to_ascii_lowercase();
+        assert!(l.is_shared_with(&t));
+        let u = t.to_ascii_uppercase();
+        assert_eq!("ALREADY LOWER CASE, WITH üNïCöDé", &*u);
+        assert!(u.eq_ignore_ascii_case(&t));
+        assert!(!u.eq_ignore_ascii_case(&"ALREADY LOWER CASE, WITH ÜNÏCÖDÉ".to_tendril()));
+
+        let mut s = u.clone();
+        s.make_ascii_uppercase();
+        assert!(s.is_shared_with(&u));
+        s.make_ascii_lowercase();
+        assert!(!s.is_shared_with(&u));
+        assert_eq!(t, s);
+
+        let mut i = "MiXeD".to_tendril();
+        i.make_ascii_lowercase();
+        assert_eq!("mixed", &*i);
+
+        let a: Tendril<fmt::ASCII> = b"Hello".to_tendril().try_reinterpret().unwrap();
+        assert_eq!(b"HELLO", &**a.to_uppercase().as_bytes());
+        assert_eq!(b"hello", &**a.to_lowercase().as_bytes());
+    }
+
+    #[test]
+    fn unicode_case() {
+        let t = "straße and ῼ, long enough".to_tendril();
+        assert_eq!("STRASSE AND ΩΙ, LONG ENOUGH", &*t.to_uppercase());
+        assert!(t.to_lowercase() != t);
+
+        let t = "σ is already lower case".to_tendril();
+        assert!(t.to_lowercase().is_shared_with(&t));
+        assert_eq!("ὀδυσσεύς", &*"ὈΔΥΣΣΕΎΣ".to_tendril().to_lowercase());
+    }
+
+    #[test]
+    fn latin1_case() {
+        let t = latin1(b"\xc0 la fran\xe7aise, stra\xdfe");
+        assert!(latin1(b"\xe0 la fran\xe7aise, stra\xdfe") == t.to_lowercase());
+        assert!(latin1(b"\xc0 LA FRAN\xc7AISE, STRASSE") == t.try_to_uppercase().unwrap());
+        assert!(latin1(b"caf\xff").try_to_uppercase().is_none());
+        assert!(latin1(b"\xb5").try_to_uppercase().is_none());
+
+        let l = latin1(b"nothing to change here");
+        assert!(l.to_lowercase().is_shared_with(&l));
+    }
+}
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading from a tendril through `io::Read`.
+
+use std::cmp;
+use std::io::{self, BufRead, Read, Seek, SeekFrom};
+
+use fmt;
+use tendril::{Atomicity, NonAtomic, Tendril};
+
+/// A cursor over the bytes of a `Tendril`, like `io::Cursor`.
+///
+/// Besides `Read`, `BufRead` and `Seek`, it can hand out parts of the
+/// tendril as subtendrils which share its buffer. These are always
+/// `ByteTendril`s, since a byte position needn't fall on a character
+/// boundary; use `try_reinterpret` to get back to the original format.
+pub struct TendrilCursor<F, A = NonAtomic>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    tendril: Tendril<F, A>,
+    pos: u64,
+}
+
+impl<F, A> TendrilCursor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Create a cursor at the start of a `Tendril`.
+    #[inline]
+    pub fn new(tendril: Tendril<F, A>) -> TendrilCursor<F, A> {
+        TendrilCursor { tendril, pos: 0 }
+    }
+
+    /// Get the current position, in bytes.
+    #[inline]
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+
+    /// Set the current position, in bytes. It may be past the end.
+    #[inline]
+    pub fn set_position(&mut self, pos: u64) {
+        self.pos = pos;
+    }
+
+    /// Get a reference to the underlying `Tendril`.
+    #[inline]
+    pub fn get_ref(&self) -> &Tendril<F, A> {
+        &self.tendril
+    }
+
+    /// Unwrap the underlying `Tendril`.
+    #[inline]
+    pub fn into_inner(self) -> Tendril<F, A> {
+        self.tendril
+    }
+
+    /// Get the bytes after the current position.
+    #[inline]
+    fn remaining(&self) -> &[u8] {
+        let bytes: &[u8] = self.tendril.as_bytes();
+        let start = cmp::min(self.pos, bytes.len() as u64) as usize;
+        &bytes[start..]
+    }
+
+    /// Take `n` bytes after the current position as a subtendril.
+    #[inline]
+    fn take(&mut self, n: usize) -> Tendril<fmt::Bytes, A> {
+        if n == 0 {
+            return Tendril::new();
+        }
+        let t = self
+            .tendril
+            .as_bytes()
+            .subtendril(self.pos as u32, n as u32);
+        self.pos += n as u64;
+        t
+    }
+
+    /// Read up to `n` bytes, sharing the buffer. The result is empty at
+    /// the end.
+    #[inline]
+    pub fn read_tendril(&mut self, n: u32) -> Tendril<fmt::Bytes, A> {
+        let n = cmp::min(n as usize, self.remaining().len());
+        self.take(n)
+    }
+
+    /// Read up to and including the next `byte`, or to the end if there
+    /// isn't one, sharing the buffer. The result is empty at the end.
+    #[inline]
+    pub fn read_until_tendril(&mut self, byte: u8) -> Tendril<fmt::Bytes, A> {
+        let rest = self.remaining();
+        let n = match rest.iter().position(|&b| b == byte) {
+            Some(i) => i + 1,
+            None => rest.len(),
+        };
+        self.take(n)
+    }
+}
+
+impl<F, A> Read for TendrilCursor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.remaining().read(buf)?;
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<F, A> BufRead for TendrilCursor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        Ok(self.remaining())
+    }
+
+    #[inline]
+    fn consume(&mut self, amt: usize) {
+        self.pos += amt as u64;
+    }
+}
+
+impl<F, A> Seek for TendrilCursor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    fn seek(&mut self, style: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match style {
+            SeekFrom::Start(n) => {
+                self.pos = n;
+                return Ok(n);
+            }
+            SeekFrom::End(n) => (self.tendril.len32() as u64, n),
+            SeekFrom::Current(n) => (self.pos, n),
+        };
+        let new_pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match new_pos {
+            Some(n) => {
+                self.pos = n;
+                Ok(n)
+            }
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TendrilCursor;
+    use std::io::{BufRead, Read, Seek, SeekFrom};
+    use tendril::SliceExt;
+
+    #[test]
+    fn read() {
+        let t = "first line\nsecond line\nlast".to_tendril();
+        let mut c = TendrilCursor::new(t.clone());
+        let mut buf = [0; 6];
+        assert_eq!(6, c.read(&mut buf).unwrap());
+        assert_eq!(b"first ", &buf);
+        let mut line = String::new();
+        c.read_line(&mut line).unwrap();
+        assert_eq!("line\n", line);
+        let lines: Vec<String> = c.lines().map(Result::unwrap).collect();
+        assert_eq!(vec!["second line", "last"], lines);
+    }
+
+    #[test]
+    fn read_tendril() {
+        let t = "first line is long\nsecond line\nlast".to_tendril();
+        let mut c = TendrilCursor::new(t.clone());
+        let first = c.read_until_tendril(b'\n');
+        assert_eq!(b"first line is long\n", &*first);
+        assert!(first.is_shared_with(t.as_bytes()));
+        assert_eq!(b"second", &*c.read_tendril(6));
+        assert_eq!(b" line\n", &*c.read_until_tendril(b'\n'));
+        assert_eq!(b"last", &*c.read_until_tendril(b'\n'));
+        assert!(c.read_until_tendril(b'\n').is_empty());
+        assert!(c.read_tendril(10).is_empty());
+
+        c.set_position(100);
+        assert!(c.read_tendril(10).is_empty());
+        assert_eq!(0, c.read(&mut [0; 4]).unwrap());
+    }
+
+    #[test]
+    fn seek() {
+        let mut c = TendrilCursor::new(b"0123456789".to_tendril());
+        assert_eq!(7, c.seek(SeekFrom::End(-3)).unwrap());
+        assert_eq!(b"789", &*c.read_tendril(5));
+        assert_eq!(4, c.seek(SeekFrom::Current(-6)).unwrap());
+        assert_eq!(b"45", &*c.read_tendril(2));
+        assert_eq!(2, c.seek(SeekFrom::Start(2)).unwrap());
+        assert_eq!(b"2", &*c.read_tendril(1));
+        assert!(c.seek(SeekFrom::Current(-4)).is_err());
+        assert_eq!(3, c.position());
+        assert_eq!(20, c.seek(SeekFrom::End(10)).unwrap());
+        assert_eq!(b"0123456789", &*c.into_inner());
+    }
+}
@@ -19,18 +19,22 @@
 //! the format sneaks in. For that reason, these traits require
 //! `unsafe impl`.
 
-use std::default::Default;
-use std::{char, mem, str};
+use core::default::Default;
+use core::fmt as strfmt;
+use core::marker::PhantomData;
+use core::{char, mem, str};
 
 use futf::{self, Codepoint, Meaning};
+use validate;
 
 /// Implementation details.
 ///
 /// You don't need these unless you are implementing
 /// a new format.
 pub mod imp {
-    use std::default::Default;
-    use std::{iter, mem, slice};
+    use core::default::Default;
+    use core::{char, iter, mem, slice};
+    use futf::{self, Codepoint, Meaning};
 
     /// Describes how to fix up encodings when concatenating.
     ///
@@ -83,6 +87,121 @@ pub mod imp {
             }
         }
     }
+
+    /// Read the 16-bit code unit at byte index `i`.
+    #[inline(always)]
+    pub fn utf16_unit(buf: &[u8], i: usize, big_endian: bool) -> u16 {
+        let (a, b) = (buf[i] as u16, buf[i + 1] as u16);
+        if big_endian {
+            (a << 8) | b
+        } else {
+            (b << 8) | a
+        }
+    }
+
+    pub struct Utf16CharIndices<'a> {
+        buf: &'a [u8],
+        pos: usize,
+        big_endian: bool,
+    }
+
+    impl<'a> Iterator for Utf16CharIndices<'a> {
+        type Item = (usize, char);
+
+        #[inline]
+        fn next(&mut self) -> Option<(usize, char)> {
+            let start = self.pos;
+            if start >= self.buf.len() {
+                return None;
+            }
+            let hi = utf16_unit(self.buf, start, self.big_endian) as u32;
+            self.pos += 2;
+            let n = match hi {
+                0xD800..=0xDBFF => {
+                    let lo = utf16_unit(self.buf, self.pos, self.big_endian) as u32;
+                    self.pos += 2;
+                    0x10000 + ((hi - 0xD800) << 10) + (lo - 0xDC00)
+                }
+                _ => hi,
+            };
+            Some((start, unsafe { from_u32_unchecked(n) }))
+        }
+    }
+
+    pub struct Cesu8CharIndices<'a> {
+        buf: &'a [u8],
+        pos: usize,
+        modified: bool,
+    }
+
+    impl<'a> Iterator for Cesu8CharIndices<'a> {
+        type Item = (usize, char);
+
+        #[inline]
+        fn next(&mut self) -> Option<(usize, char)> {
+            const ERR: &str = "CESU8: internal error";
+
+            let start = self.pos;
+            let b = *self.buf.get(start)?;
+            if b < 0x80 {
+                self.pos += 1;
+                return Some((start, b as char));
+            }
+            if self.modified && b == 0xC0 {
+                self.pos += 2;
+                return Some((start, '\0'));
+            }
+
+            let codept = futf::classify(self.buf, start).expect(ERR);
+            self.pos += codept.bytes.len();
+            let ch = match codept.meaning {
+                Meaning::Whole(c) => c,
+                Meaning::LeadSurrogate(hi) => match futf::classify(self.buf, self.pos) {
+                    Some(Codepoint {
+                        meaning: Meaning::TrailSurrogate(lo),
+                        bytes,
+                        ..
+                    }) => {
+                        self.pos += bytes.len();
+                        let n = 0x10000 + ((hi as u32) << 10) + (lo as u32);
+                        char::from_u32(n).expect(ERR)
+                    }
+                    _ => panic!("{}", ERR),
+                },
+                _ => panic!("{}", ERR),
+            };
+            Some((start, ch))
+        }
+    }
+
+    impl<'a> Cesu8CharIndices<'a> {
+        /// # Safety
+        ///
+        /// The buffer must be valid CESU-8, or valid Modified UTF-8 if
+        /// `modified` is set.
+        #[inline]
+        pub unsafe fn new(buf: &'a [u8], modified: bool) -> Cesu8CharIndices<'a> {
+            Cesu8CharIndices {
+                buf,
+                pos: 0,
+                modified,
+            }
+        }
+    }
+
+    impl<'a> Utf16CharIndices<'a> {
+        /// # Safety
+        ///
+        /// The buffer must be valid UTF-16 in the given byte order.
+        #[inline]
+        pub unsafe fn new(buf: &'a [u8], big_endian: bool) -> Utf16CharIndices<'a> {
+            Utf16CharIndices {
+                buf,
+                pos: 0,
+                big_endian,
+            }
+        }
+    }
 }
 
 /// Trait for format marker types.
@@ -119,6 +238,23 @@ pub unsafe trait Format {
         <Self as Format>::validate(buf)
     }
 
+    /// Check whether a subsequence may start `offset` bytes into a valid
+    /// buffer.
+    ///
+    /// Formats with code units of more than one byte can't tell this from
+    /// the subsequence alone. The default accepts any offset.
+    #[inline(always)]
+    fn validate_offset(_offset: usize) -> bool {
+        true
+    }
+
+    /// Is the concatenation of two valid buffers always valid, after any
+    /// fixup?
+    ///
+    /// If not, pushing onto a `Tendril` checks the whole buffer rather
+    /// than the pushed bytes alone. The default is `true`.
+    const CONCAT_SAFE: bool = true;
+
     /// Compute any fixup needed when concatenating buffers.
     ///
     /// The default is to do nothing.
@@ -158,6 +294,20 @@ pub unsafe trait SliceFormat: Format + Sized {
     type Slice: ?Sized + Slice;
 }
 
+/// Indicates a format in which every ASCII byte is a whole character.
+///
+/// Runs of ASCII can then be scanned as plain bytes.
+///
+/// # Safety
+///
+/// Splitting a valid buffer just before or after an ASCII byte must give
+/// two valid buffers. If `SINGLE_BYTE` is `true`, so must splitting it
+/// between any two bytes.
+pub unsafe trait AsciiCompatible: Format {
+    /// Is every byte a whole character, as in `Latin1`?
+    const SINGLE_BYTE: bool = false;
+}
+
 /// Indicates a format which contains characters from Unicode
 /// (all of it, or some proper subset).
 pub unsafe trait CharFormat<'a>: Format {
@@ -178,6 +328,66 @@ pub unsafe trait CharFormat<'a>: Format {
         F: FnOnce(&[u8]);
 }
 
+/// Indicates a format which can be decoded to and encoded from code points,
+/// so that tendrils can be transcoded between any two such formats.
+///
+/// Every `CharFormat` is one. Formats like `WTF8` can also carry unpaired
+/// surrogates, which are decoded as their own code points.
+///
+/// # Safety
+///
+/// `encode` must only produce bytes which are valid for the format.
+pub unsafe trait Transcodable: Format {
+    /// Decode the buffer, passing the byte index and value of each code
+    /// point to a continuation, until it returns `false`.
+    ///
+    /// A surrogate pair must be decoded as the single code point it
+    /// stands for.
+    ///
+    /// # Safety
+    ///
+    /// You may assume the buffer is *already validated* for `Format`.
+    unsafe fn decode<C>(buf: &[u8], cont: C)
+    where
+        C: FnMut(usize, u32) -> bool;
+
+    /// Encode the code point as bytes and pass them to a continuation.
+    ///
+    /// Returns `Err(())` iff the code point cannot be represented.
+    #[allow(clippy::result_unit_err)]
+    fn encode<C>(code_point: u32, cont: C) -> Result<(), ()>
+    where
+        C: FnOnce(&[u8]);
+}
+
+unsafe impl<F> Transcodable for F
+where
+    F: for<'a> CharFormat<'a>,
+{
+    #[inline]
+    unsafe fn decode<C>(buf: &[u8], mut cont: C)
+    where
+        C: FnMut(usize, u32) -> bool,
+    {
+        for (i, c) in F::char_indices(buf) {
+            if !cont(i, c as u32) {
+                return;
+            }
+        }
+    }
+
+    #[inline]
+    fn encode<C>(code_point: u32, cont: C) -> Result<(), ()>
+    where
+        C: FnOnce(&[u8]),
+    {
+        match char::from_u32(code_point) {
+            Some(c) => F::encode_char(c, cont),
+            None => Err(()),
+        }
+    }
+}
+
 /// Indicates a Rust slice type that is represented in memory as bytes.
 pub unsafe trait Slice {
     /// Access the raw bytes of the slice.
@@ -209,6 +419,10 @@ unsafe impl Format for Bytes {
     }
 }
 
+unsafe impl AsciiCompatible for Bytes {
+    const SINGLE_BYTE: bool = true;
+}
+
 unsafe impl SliceFormat for Bytes {
     type Slice = [u8];
 }
@@ -237,7 +451,7 @@ pub struct ASCII;
 unsafe impl Format for ASCII {
     #[inline]
     fn validate(buf: &[u8]) -> bool {
-        buf.iter().all(|&n| n <= 127)
+        validate::is_ascii(buf)
     }
 
     #[inline(always)]
@@ -258,6 +472,11 @@ unsafe impl Format for ASCII {
 
 unsafe impl SubsetOf<UTF8> for ASCII {}
 unsafe impl SubsetOf<Latin1> for ASCII {}
+unsafe impl SubsetOf<CESU8> for ASCII {}
+
+unsafe impl AsciiCompatible for ASCII {
+    const SINGLE_BYTE: bool = true;
+}
 
 unsafe impl<'a> CharFormat<'a> for ASCII {
     type Iter = imp::SingleByteCharIndices<'a>;
@@ -288,7 +507,7 @@ pub struct UTF8;
 unsafe impl Format for UTF8 {
     #[inline]
     fn validate(buf: &[u8]) -> bool {
-        str::from_utf8(buf).is_ok()
+        validate::is_utf8(buf)
     }
 
     #[inline]
@@ -327,6 +546,8 @@ unsafe impl Format for UTF8 {
 
 unsafe impl SubsetOf<WTF8> for UTF8 {}
 
+unsafe impl AsciiCompatible for UTF8 {}
+
 unsafe impl SliceFormat for UTF8 {
     type Slice = str;
 }
@@ -386,6 +607,11 @@ unsafe impl Format for WTF8 {
         let mut i = 0;
         let mut prev_lead = false;
         while i < buf.len() {
+            if buf[i] < 0x80 {
+                i += validate::ascii_run_len(&buf[i..]);
+                prev_lead = false;
+                continue;
+            }
             let codept = unwrap_or_return!(futf::classify(buf, i), false);
             if !wtf8_meaningful(codept.meaning) {
                 return false;
@@ -464,6 +690,51 @@ unsafe impl Format for WTF8 {
     }
 }
 
+unsafe impl AsciiCompatible for WTF8 {}
+
+unsafe impl Transcodable for WTF8 {
+    #[inline]
+    unsafe fn decode<C>(buf: &[u8], mut cont: C)
+    where
+        C: FnMut(usize, u32) -> bool,
+    {
+        const ERR: &str = "WTF8: internal error";
+
+        let mut i = 0;
+        while i < buf.len() {
+            let codept = futf::classify(buf, i).expect(ERR);
+            let n = match codept.meaning {
+                Meaning::Whole(c) => c as u32,
+                Meaning::LeadSurrogate(n) => 0xD800 + n as u32,
+                Meaning::TrailSurrogate(n) => 0xDC00 + n as u32,
+                _ => panic!("{}", ERR),
+            };
+            if !cont(i, n) {
+                return;
+            }
+            i += codept.bytes.len();
+        }
+    }
+
+    #[inline]
+    fn encode<C>(code_point: u32, cont: C) -> Result<(), ()>
+    where
+        C: FnOnce(&[u8]),
+    {
+        match code_point {
+            0xD800..=0xDFFF => {
+                cont(&[
+                    0xE0 | (code_point >> 12) as u8,
+                    0x80 | ((code_point >> 6) & 0x3F) as u8,
+                    0x80 | (code_point & 0x3F) as u8,
+                ]);
+                Ok(())
+            }
+            _ => UTF8::encode(code_point, cont),
+        }
+    }
+}
+
 /// Marker type for the single-byte encoding of the first 256 Unicode codepoints.
 ///
 /// This is IANA's "ISO-8859-1". It's ISO's "ISO 8859-1" with the addition of the
@@ -496,6 +767,10 @@ unsafe impl Format for Latin1 {
     }
 }
 
+unsafe impl AsciiCompatible for Latin1 {
+    const SINGLE_BYTE: bool = true;
+}
+
 unsafe impl<'a> CharFormat<'a> for Latin1 {
     type Iter = imp::SingleByteCharIndices<'a>;
 
@@ -517,3 +792,682 @@ unsafe impl<'a> CharFormat<'a> for Latin1 {
         Ok(())
     }
 }
+
+#[inline]
+fn is_lead_surrogate(n: u16) -> bool {
+    (0xD800..=0xDBFF).contains(&n)
+}
+
+#[inline]
+fn is_trail_surrogate(n: u16) -> bool {
+    (0xDC00..=0xDFFF).contains(&n)
+}
+
+#[inline]
+fn utf16_validate(buf: &[u8], big_endian: bool) -> bool {
+    if buf.len() & 1 != 0 {
+        return false;
+    }
+    let mut i = 0;
+    while i < buf.len() {
+        let n = imp::utf16_unit(buf, i, big_endian);
+        i += 2;
+        if is_lead_surrogate(n) {
+            if i >= buf.len() || !is_trail_surrogate(imp::utf16_unit(buf, i, big_endian)) {
+                return false;
+            }
+            i += 2;
+        } else if is_trail_surrogate(n) {
+            return false;
+        }
+    }
+    true
+}
+
+#[inline]
+fn utf16_validate_prefix(buf: &[u8], big_endian: bool) -> bool {
+    buf.len() & 1 == 0
+        && (buf.is_empty() || !is_lead_surrogate(imp::utf16_unit(buf, buf.len() - 2, big_endian)))
+}
+
+#[inline]
+fn utf16_validate_suffix(buf: &[u8], big_endian: bool) -> bool {
+    buf.len() & 1 == 0
+        && (buf.is_empty() || !is_trail_surrogate(imp::utf16_unit(buf, 0, big_endian)))
+}
+
+#[inline]
+fn utf16_encode_char<F>(ch: char, big_endian: bool, cont: F)
+where
+    F: FnOnce(&[u8]),
+{
+    let mut units = [0_u16; 2];
+    let units = ch.encode_utf16(&mut units);
+    let mut bytes = [0_u8; 4];
+    for (i, &n) in units.iter().enumerate() {
+        let (hi, lo) = ((n >> 8) as u8, n as u8);
+        if big_endian {
+            bytes[2 * i] = hi;
+            bytes[2 * i + 1] = lo;
+        } else {
+            bytes[2 * i] = lo;
+            bytes[2 * i + 1] = hi;
+        }
+    }
+    cont(&bytes[..2 * units.len()])
+}
+
+macro_rules! utf16_formats {
+    ($(#[$utf16_doc:meta] $utf16:ident, #[$wtf16_doc:meta] $wtf16:ident, $big_endian:expr;)*) => {$(
+        #[$utf16_doc]
+        ///
+        /// Surrogates must be paired. Concatenation never needs a fixup:
+        /// a buffer may not end in half a pair, so none can be split.
+        #[derive(Copy, Clone, Default, Debug)]
+        pub struct $utf16;
+
+        unsafe impl Format for $utf16 {
+            #[inline]
+            fn validate(buf: &[u8]) -> bool {
+                utf16_validate(buf, $big_endian)
+            }
+
+            #[inline]
+            fn validate_prefix(buf: &[u8]) -> bool {
+                utf16_validate_prefix(buf, $big_endian)
+            }
+
+            #[inline]
+            fn validate_suffix(buf: &[u8]) -> bool {
+                utf16_validate_suffix(buf, $big_endian)
+            }
+
+            #[inline]
+            fn validate_subseq(buf: &[u8]) -> bool {
+                <Self as Format>::validate_prefix(buf) && <Self as Format>::validate_suffix(buf)
+            }
+
+            #[inline]
+            fn validate_offset(offset: usize) -> bool {
+                offset & 1 == 0
+            }
+        }
+
+        unsafe impl SubsetOf<$wtf16> for $utf16 {}
+
+        unsafe impl<'a> CharFormat<'a> for $utf16 {
+            type Iter = imp::Utf16CharIndices<'a>;
+
+            #[inline]
+            unsafe fn char_indices(buf: &'a [u8]) -> imp::Utf16CharIndices<'a> {
+                imp::Utf16CharIndices::new(buf, $big_endian)
+            }
+
+            #[inline]
+            fn encode_char<F>(ch: char, cont: F) -> Result<(), ()>
+            where
+                F: FnOnce(&[u8]),
+            {
+                utf16_encode_char(ch, $big_endian, cont);
+                Ok(())
+            }
+        }
+
+        #[$wtf16_doc]
+        ///
+        /// Any sequence of 16-bit code units is allowed, including unpaired
+        /// surrogates. Concatenating a lone lead surrogate with a lone trail
+        /// surrogate forms a pair without changing any bytes, so no fixup is
+        /// needed.
+        #[derive(Copy, Clone, Default, Debug)]
+        pub struct $wtf16;
+
+        unsafe impl Format for $wtf16 {
+            #[inline]
+            fn validate(buf: &[u8]) -> bool {
+                buf.len() & 1 == 0
+            }
+
+            #[inline]
+            fn validate_offset(offset: usize) -> bool {
+                offset & 1 == 0
+            }
+        }
+    )*};
+}
+
+utf16_formats! {
+    /// Marker type for UTF-16 text, little-endian.
+    UTF16LE,
+    /// Marker type for potentially ill-formed UTF-16 text, little-endian.
+    WTF16LE,
+    false;
+
+    /// Marker type for UTF-16 text, big-endian.
+    UTF16BE,
+    /// Marker type for potentially ill-formed UTF-16 text, big-endian.
+    WTF16BE,
+    true;
+}
+
+/// Is there a Modified UTF-8 encoding of NUL at index `i`?
+#[inline]
+fn mutf8_nul_at(buf: &[u8], i: usize) -> bool {
+    buf.get(i) == Some(&0xC0) && buf.get(i + 1) == Some(&0x80)
+}
+
+#[inline]
+fn cesu8_validate(buf: &[u8], modified: bool) -> bool {
+    let mut i = 0;
+    while i < buf.len() {
+        if modified {
+            if buf[i] == 0 {
+                return false;
+            }
+            if mutf8_nul_at(buf, i) {
+                i += 2;
+                continue;
+            }
+        }
+        let codept = unwrap_or_return!(futf::classify(buf, i), false);
+        i += codept.bytes.len();
+        match codept.meaning {
+            Meaning::Whole(_) if codept.bytes.len() < 4 => {}
+            Meaning::LeadSurrogate(_) => match futf::classify(buf, i) {
+                Some(Codepoint {
+                    meaning: Meaning::TrailSurrogate(_),
+                    bytes,
+                    ..
+                }) => i += bytes.len(),
+                _ => return false,
+            },
+            _ => return false,
+        }
+    }
+    true
+}
+
+#[inline]
+fn cesu8_validate_prefix(buf: &[u8], modified: bool) -> bool {
+    if buf.is_empty() {
+        return true;
+    }
+    if modified && buf.len() >= 2 && mutf8_nul_at(buf, buf.len() - 2) {
+        return true;
+    }
+    match futf::classify(buf, buf.len() - 1) {
+        Some(c) => matches!(c.meaning, Meaning::Whole(_) | Meaning::TrailSurrogate(_)),
+        None => false,
+    }
+}
+
+#[inline]
+fn cesu8_validate_suffix(buf: &[u8], modified: bool) -> bool {
+    if buf.is_empty() || (modified && mutf8_nul_at(buf, 0)) {
+        return true;
+    }
+    match futf::classify(buf, 0) {
+        Some(c) => matches!(c.meaning, Meaning::Whole(_) | Meaning::LeadSurrogate(_)),
+        None => false,
+    }
+}
+
+#[inline]
+fn cesu8_encode_char<F>(ch: char, modified: bool, cont: F)
+where
+    F: FnOnce(&[u8]),
+{
+    if modified && ch == '\0' {
+        return cont(&[0xC0, 0x80]);
+    }
+    if (ch as u32) < 0x10000 {
+        return cont(ch.encode_utf8(&mut [0_u8; 4]).as_bytes());
+    }
+
+    let mut units = [0_u16; 2];
+    let mut bytes = [0_u8; 6];
+    for (i, &n) in ch.encode_utf16(&mut units).iter().enumerate() {
+        bytes[3 * i] = 0xE0 | (n >> 12) as u8;
+        bytes[3 * i + 1] = 0x80 | ((n >> 6) & 0x3F) as u8;
+        bytes[3 * i + 2] = 0x80 | (n & 0x3F) as u8;
+    }
+    cont(&bytes)
+}
+
+macro_rules! cesu8_formats {
+    ($($(#[$doc:meta])* $name:ident, $modified:expr;)*) => {$(
+        $(#[$doc])*
+        #[derive(Copy, Clone, Default, Debug)]
+        pub struct $name;
+
+        unsafe impl Format for $name {
+            #[inline]
+            fn validate(buf: &[u8]) -> bool {
+                cesu8_validate(buf, $modified)
+            }
+
+            #[inline]
+            fn validate_prefix(buf: &[u8]) -> bool {
+                cesu8_validate_prefix(buf, $modified)
+            }
+
+            #[inline]
+            fn validate_suffix(buf: &[u8]) -> bool {
+                cesu8_validate_suffix(buf, $modified)
+            }
+
+            #[inline]
+            fn validate_subseq(buf: &[u8]) -> bool {
+                <Self as Format>::validate_prefix(buf) && <Self as Format>::validate_suffix(buf)
+            }
+        }
+
+        unsafe impl AsciiCompatible for $name {}
+
+        unsafe impl<'a> CharFormat<'a> for $name {
+            type Iter = imp::Cesu8CharIndices<'a>;
+
+            #[inline]
+            unsafe fn char_indices(buf: &'a [u8]) -> imp::Cesu8CharIndices<'a> {
+                imp::Cesu8CharIndices::new(buf, $modified)
+            }
+
+            #[inline]
+            fn encode_char<F>(ch: char, cont: F) -> Result<(), ()>
+            where
+                F: FnOnce(&[u8]),
+            {
+                cesu8_encode_char(ch, $modified, cont);
+                Ok(())
+            }
+        }
+    )*};
+}
+
+cesu8_formats! {
+    /// Marker type for CESU-8 text.
+    ///
+    /// This is UTF-8, except that characters outside the Basic Multilingual
+    /// Plane are written as a UTF-16 surrogate pair, each half taking three
+    /// bytes. Surrogates must be paired, so concatenation never needs a
+    /// fixup.
+    ///
+    /// See [Unicode Technical Report #26](https://www.unicode.org/reports/tr26/).
+    CESU8,
+    false;
+
+    /// Marker type for the Modified UTF-8 used by Java class files and JNI.
+    ///
+    /// This is CESU-8, except that NUL is written as the overlong sequence
+    /// `C0 80`, so that a zero byte never appears.
+    MUTF8,
+    true;
+}
+
+/// An invariant on the contents of a tendril, on top of its format.
+///
+/// Used with `Refined`. Each check may assume the buffer is valid for the
+/// underlying format, and the prefix, suffix and subsequence checks may
+/// assume it is part of a buffer which satisfies the invariant.
+///
+/// Unless `CONCAT_SAFE` is set, every push onto the tendril rechecks the
+/// whole buffer, which costs time linear in its length.
+///
+/// The invariant must also hold for the empty buffer, since `Tendril::new`,
+/// `Default` and `clear` make empty tendrils without asking the validator.
+/// A rule like "a valid XML Name" can't exclude the empty string, so an
+/// empty tendril has to stand for "no name yet".
+///
+/// This trait is safe to implement: if it's wrong, the invariant may be
+/// broken, but memory safety still rests on the underlying format.
+pub trait Validator {
+    /// Does the invariant hold for the concatenation of any two buffers
+    /// which satisfy it?
+    ///
+    /// If so, a push only checks the pushed bytes, so `"a1"` followed by
+    /// `"2b"` is rejected under a rule that names can't start with a digit.
+    /// The default is `false`, which checks the whole buffer after a push.
+    const CONCAT_SAFE: bool = false;
+
+    /// Check whether the buffer satisfies the invariant.
+    fn validate(buf: &[u8]) -> bool;
+
+    /// Check whether a prefix of a valid buffer satisfies the invariant.
+    #[inline]
+    fn validate_prefix(buf: &[u8]) -> bool {
+        Self::validate(buf)
+    }
+
+    /// Check whether a suffix of a valid buffer satisfies the invariant.
+    #[inline]
+    fn validate_suffix(buf: &[u8]) -> bool {
+        Self::validate(buf)
+    }
+
+    /// Check whether a subsequence of a valid buffer satisfies the
+    /// invariant.
+    #[inline]
+    fn validate_subseq(buf: &[u8]) -> bool {
+        Self::validate(buf)
+    }
+}
+
+/// Marker type for text in the format `F` which also satisfies the
+/// invariant `V`.
+///
+/// The invariant is checked when a tendril is made or reinterpreted, and
+/// when bytes are pushed, but not by operations which can't break it, such
+/// as `as_superset` back to `F`.
+///
+/// This is not a `CharFormat`, even when `F` is, since popping or encoding
+/// one character at a time can't check an invariant on the whole buffer.
+/// Go through `F` with `as_superset` and `try_into_subset` instead.
+pub struct Refined<F, V> {
+    marker: PhantomData<(F, V)>,
+}
+
+impl<F, V> Clone for Refined<F, V> {
+    #[inline(always)]
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F, V> Copy for Refined<F, V> {}
+
+impl<F, V> Default for Refined<F, V> {
+    #[inline(always)]
+    fn default() -> Self {
+        Refined {
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<F, V> strfmt::Debug for Refined<F, V>
+where
+    F: Default + strfmt::Debug,
+{
+    fn fmt(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        write!(f, "Refined<{:?}>", F::default())
+    }
+}
+
+unsafe impl<F, V> Format for Refined<F, V>
+where
+    F: Format,
+    V: Validator,
+{
+    #[inline]
+    fn validate(buf: &[u8]) -> bool {
+        F::validate(buf) && V::validate(buf)
+    }
+
+    #[inline]
+    fn validate_prefix(buf: &[u8]) -> bool {
+        F::validate_prefix(buf) && V::validate_prefix(buf)
+    }
+
+    #[inline]
+    fn validate_suffix(buf: &[u8]) -> bool {
+        F::validate_suffix(buf) && V::validate_suffix(buf)
+    }
+
+    #[inline]
+    fn validate_subseq(buf: &[u8]) -> bool {
+        F::validate_subseq(buf) && V::validate_subseq(buf)
+    }
+
+    #[inline]
+    fn validate_offset(offset: usize) -> bool {
+        F::validate_offset(offset)
+    }
+
+    const CONCAT_SAFE: bool = F::CONCAT_SAFE && V::CONCAT_SAFE;
+
+    #[inline]
+    unsafe fn fixup(lhs: &[u8], rhs: &[u8]) -> imp::Fixup {
+        F::fixup(lhs, rhs)
+    }
+}
+
+unsafe impl<F, V> SubsetOf<F> for Refined<F, V>
+where
+    F: Format,
+    V: Validator,
+{
+    #[inline]
+    fn revalidate_subset(x: &[u8]) -> bool {
+        V::validate(x)
+    }
+}
+
+/// Define a format for ASCII text restricted to a class of bytes.
+///
+/// This writes the unsafe `Format` and `CharFormat` impls for you, along with
+/// `SubsetOf` impls for `ASCII`, `UTF8` and `Latin1`. Any subsequence of a
+/// valid buffer is valid, so no fixups are needed. The predicate is only
+/// ever asked about ASCII bytes; everything else is rejected.
+///
+/// ```
+/// # #[macro_use] extern crate tendril;
+/// # use tendril::Tendril;
+/// byte_class_format! {
+///     /// Printable ASCII, without control characters.
+///     pub struct PrintableASCII: |b| b >= 0x20 && b < 0x7F;
+/// }
+///
+/// # fn main() {
+/// let t: Tendril<PrintableASCII> = Tendril::try_from_byte_slice(b"hello").unwrap();
+/// assert!(Tendril::<PrintableASCII>::try_from_byte_slice(b"\t").is_err());
+/// # }
+/// ```
+#[macro_export]
+macro_rules! byte_class_format {
+    ($($(#[$attr:meta])* $vis:vis struct $name:ident: |$b:ident| $pred:expr;)*) => {$(
+        $(#[$attr])*
+        #[derive(Copy, Clone, Default, Debug)]
+        $vis struct $name;
+
+        impl $name {
+            /// Is this byte allowed in the format?
+            #[inline]
+            pub fn contains_byte($b: u8) -> bool {
+                $b < 0x80 && $pred
+            }
+        }
+
+        unsafe impl $crate::fmt::Format for $name {
+            #[inline]
+            fn validate(buf: &[u8]) -> bool {
+                buf.iter().all(|&b| $name::contains_byte(b))
+            }
+
+            #[inline(always)]
+            fn validate_prefix(_: &[u8]) -> bool {
+                true
+            }
+
+            #[inline(always)]
+            fn validate_suffix(_: &[u8]) -> bool {
+                true
+            }
+
+            #[inline(always)]
+            fn validate_subseq(_: &[u8]) -> bool {
+                true
+            }
+        }
+
+        unsafe impl $crate::fmt::SubsetOf<$crate::fmt::ASCII> for $name {}
+        unsafe impl $crate::fmt::SubsetOf<$crate::fmt::UTF8> for $name {}
+        unsafe impl $crate::fmt::SubsetOf<$crate::fmt::Latin1> for $name {}
+        unsafe impl $crate::fmt::AsciiCompatible for $name {
+            const SINGLE_BYTE: bool = true;
+        }
+
+        unsafe impl<'a> $crate::fmt::CharFormat<'a> for $name {
+            type Iter = $crate::fmt::imp::SingleByteCharIndices<'a>;
+
+            #[inline]
+            unsafe fn char_indices(buf: &'a [u8]) -> $crate::fmt::imp::SingleByteCharIndices<'a> {
+                $crate::fmt::imp::SingleByteCharIndices::new(buf)
+            }
+
+            #[inline]
+            fn encode_char<F>(ch: char, cont: F) -> Result<(), ()>
+            where
+                F: FnOnce(&[u8]),
+            {
+                let n = ch as u32;
+                if n > 0x7F || !$name::contains_byte(n as u8) {
+                    return Err(());
+                }
+                cont(&[n as u8]);
+                Ok(())
+            }
+        }
+    )*};
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Refined, Validator};
+    use tendril::{SliceExt, StrTendril, Tendril};
+
+    byte_class_format! {
+        /// RFC 7230 token characters.
+        struct HttpToken: |b| match b {
+            b'0'..=b'9' | b'a'..=b'z' | b'A'..=b'Z' => true,
+            _ => b"!#$%&'*+-.^_`|~".contains(&b),
+        };
+
+        struct Base64: |b| b.is_ascii_alphanumeric() || b == b'+' || b == b'/' || b == b'=';
+    }
+
+    #[test]
+    fn byte_class_format() {
+        assert!(HttpToken::contains_byte(b'~'));
+        assert!(!HttpToken::contains_byte(b' '));
+        assert!(!HttpToken::contains_byte(0xC0));
+
+        let mut t: Tendril<HttpToken> = b"Content-Type".to_tendril().try_reinterpret().unwrap();
+        assert!(b"Content Type"
+            .to_tendril()
+            .try_reinterpret::<HttpToken>()
+            .is_err());
+        assert!(b"caf\xC3\xA9"
+            .to_tendril()
+            .try_reinterpret::<HttpToken>()
+            .is_err());
+        assert!(t.try_push_char(':').is_err());
+        assert!(t.try_push_char('\u{e9}').is_err());
+        assert!(t.try_push_char('s').is_ok());
+        assert_eq!(Some('C'), t.pop_front_char());
+        assert_eq!("ontent-Types", &*t.into_superset::<super::UTF8>());
+
+        let b: Tendril<Base64> = b"aGVsbG8=".to_tendril().try_reinterpret().unwrap();
+        let s = "aGVsbG8=".to_tendril();
+        assert!(s.try_as_subset::<Base64>().is_ok());
+        assert_eq!(b"aGVsbG8=", &**b.as_superset::<super::ASCII>().as_bytes());
+    }
+
+    struct NoControls;
+
+    impl Validator for NoControls {
+        const CONCAT_SAFE: bool = true;
+
+        fn validate(buf: &[u8]) -> bool {
+            buf.iter().all(|&b| b >= 0x20 && b != 0x7F)
+        }
+    }
+
+    type Clean = Refined<super::UTF8, NoControls>;
+
+    #[test]
+    fn refined() {
+        assert!(Tendril::<Clean>::try_from_byte_slice(b"tab\there").is_err());
+        assert!(Tendril::<Clean>::try_from_byte_slice(b"caf\xC3").is_err());
+
+        let mut t = Tendril::<Clean>::try_from_byte_slice("café au lait".as_bytes()).unwrap();
+        assert!(t.try_subtendril(0, 4).is_err());
+        assert_eq!("café", &**t.subtendril(0, 5).as_superset::<super::UTF8>());
+
+        assert!(t.try_push_bytes(b"\n").is_err());
+        assert!(t.try_push_bytes(b"!").is_ok());
+        assert!(t.try_pop_front(1).is_ok());
+
+        let s: StrTendril = t.into_superset();
+        assert_eq!("afé au lait!", &*s);
+        assert!(s.try_into_subset::<Clean>().is_ok());
+        assert!("a\nb".to_tendril().try_into_subset::<Clean>().is_err());
+    }
+
+    struct Name;
+
+    impl Validator for Name {
+        fn validate(buf: &[u8]) -> bool {
+            match buf.first() {
+                Some(b) if b.is_ascii_digit() => false,
+                _ => buf.iter().all(|b| b.is_ascii_alphanumeric()),
+            }
+        }
+    }
+
+    #[test]
+    fn refined_whole_buffer() {
+        type XmlName = Refined<super::UTF8, Name>;
+
+        // Digits are fine after the first character, but not at the front.
+        let s: StrTendril = "a1".to_tendril();
+        let mut t = s.try_into_subset::<XmlName>().unwrap();
+        assert!("1a".to_tendril().try_into_subset::<XmlName>().is_err());
+        assert!(t.try_push_bytes(b"2b").is_ok());
+        assert!(t.try_push_bytes(b"-").is_err());
+        assert!(t.try_pop_front(1).is_err());
+        assert!(t.try_subtendril(1, 2).is_err());
+        assert_eq!("a12b", &**t.as_superset::<super::UTF8>());
+    }
+
+    struct Short;
+
+    impl Validator for Short {
+        fn validate(buf: &[u8]) -> bool {
+            buf.len() <= 4
+        }
+    }
+
+    #[test]
+    fn refined_concat() {
+        type Tag = Refined<super::UTF8, Short>;
+
+        let mut t = Tendril::<Tag>::try_from_byte_slice(b"abc").unwrap();
+        assert!(t.try_push_bytes(b"de").is_err());
+        assert_eq!("abc", &**t.as_superset::<super::UTF8>());
+        assert!(t.try_push_bytes(b"d").is_ok());
+        assert_eq!("abcd", &**t.as_superset::<super::UTF8>());
+    }
+
+    #[test]
+    #[should_panic]
+    fn refined_push_tendril() {
+        type Tag = Refined<super::UTF8, Short>;
+
+        let mut t = Tendril::<Tag>::try_from_byte_slice(b"abc").unwrap();
+        t.push_tendril(&Tendril::try_from_byte_slice(b"de").unwrap());
+    }
+
+    #[test]
+    fn refined_empty() {
+        type XmlName = Refined<super::UTF8, Name>;
+
+        // Empty tendrils are made without the validator, which accepts them.
+        assert!(Name::validate(b""));
+        let t = Tendril::<XmlName>::new();
+        assert!(t.try_reinterpret_view::<XmlName>().is_ok());
+        assert_eq!(0, Tendril::<XmlName>::default().len32());
+        let mut t = Tendril::<XmlName>::try_from_byte_slice(b"a1").unwrap();
+        t.clear();
+        assert!(t.try_reinterpret_view::<XmlName>().is_ok());
+    }
+}
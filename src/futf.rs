@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{char, slice};
+use core::{char, slice};
 
 /// Meaning of a complete or partial UTF-8 codepoint.
 ///
@@ -243,9 +243,12 @@ pub fn classify<'a>(buf: &'a [u8], idx: usize) -> Option<Codepoint<'a>> {
 #[cfg(all(test, feature = "bench"))]
 mod tests {
     use super::{all_cont, classify, decode, Byte, Meaning};
-    use std::borrow::ToOwned;
+    use alloc::borrow::ToOwned;
+    use core::str;
+    use fmt::{self, Format};
     use std::io::Write;
     use test::Bencher;
+    use validate;
 
     #[test]
     fn classify_all_bytes() {
@@ -562,4 +565,99 @@ mod tests {
             }));
         });
     }
+
+    fn mostly_ascii() -> String {
+        let line = "<p class=\"comment\">Mostly ASCII markup and text, like most \
+                    web pages, with the odd caf\u{e9} or na\u{ef}ve word.</p>\n";
+        line.repeat(32)
+    }
+
+    /// WTF-8 validation one code point at a time, as it was before the
+    /// ASCII fast path.
+    fn futf_wtf8_validate(buf: &[u8]) -> bool {
+        let mut i = 0;
+        let mut prev_lead = false;
+        while i < buf.len() {
+            let codept = match classify(buf, i) {
+                Some(c) => c,
+                None => return false,
+            };
+            i += codept.bytes.len();
+            prev_lead = match codept.meaning {
+                Meaning::Whole(_) => false,
+                Meaning::LeadSurrogate(_) => true,
+                Meaning::TrailSurrogate(_) if !prev_lead => false,
+                _ => return false,
+            };
+        }
+        true
+    }
+
+    #[bench]
+    fn std_utf8_validate(b: &mut Bencher) {
+        let text = mostly_ascii();
+        b.iter(|| assert!(str::from_utf8(text.as_bytes()).is_ok()));
+    }
+
+    #[bench]
+    fn simd_utf8_validate(b: &mut Bencher) {
+        let text = mostly_ascii();
+        b.iter(|| assert!(validate::is_utf8(text.as_bytes())));
+    }
+
+    #[bench]
+    fn std_utf8_validate_only_ascii(b: &mut Bencher) {
+        let text = "Only ASCII text here. ".repeat(128);
+        b.iter(|| assert!(str::from_utf8(text.as_bytes()).is_ok()));
+    }
+
+    #[bench]
+    fn simd_utf8_validate_only_ascii(b: &mut Bencher) {
+        let text = "Only ASCII text here. ".repeat(128);
+        b.iter(|| assert!(validate::is_utf8(text.as_bytes())));
+    }
+
+    #[bench]
+    fn std_utf8_validate_cherokee(b: &mut Bencher) {
+        b.iter(|| assert!(str::from_utf8(TEXT.as_bytes()).is_ok()));
+    }
+
+    #[bench]
+    fn simd_utf8_validate_cherokee(b: &mut Bencher) {
+        b.iter(|| assert!(validate::is_utf8(TEXT.as_bytes())));
+    }
+
+    #[bench]
+    fn futf_wtf8_validate_ascii(b: &mut Bencher) {
+        let text = mostly_ascii();
+        b.iter(|| assert!(futf_wtf8_validate(text.as_bytes())));
+    }
+
+    #[bench]
+    fn simd_wtf8_validate_ascii(b: &mut Bencher) {
+        let text = mostly_ascii();
+        b.iter(|| assert!(fmt::WTF8::validate(text.as_bytes())));
+    }
+
+    #[bench]
+    fn futf_wtf8_validate_cherokee(b: &mut Bencher) {
+        b.iter(|| assert!(futf_wtf8_validate(TEXT.as_bytes())));
+    }
+
+    #[bench]
+    fn simd_wtf8_validate_cherokee(b: &mut Bencher) {
+        b.iter(|| assert!(fmt::WTF8::validate(TEXT.as_bytes())));
+    }
+
+    #[bench]
+    fn bytewise_ascii_validate(b: &mut Bencher) {
+        let text = "Only ASCII text here. ".repeat(128);
+        b.iter(|| assert!(text.bytes().all(|n| n <= 127)));
+    }
+
+    #[bench]
+    fn simd_ascii_validate(b: &mut Bencher) {
+        let text = "Only ASCII text here. ".repeat(128);
+        b.iter(|| assert!(fmt::ASCII::validate(text.as_bytes())));
+    }
 }
@@ -0,0 +1,221 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tendrils which remember their hash value.
+
+use std::cell::Cell;
+use std::collections::hash_map::DefaultHasher;
+use std::fmt as strfmt;
+use std::hash::{self, Hasher};
+use std::ops::{Deref, DerefMut};
+
+use fmt;
+use tendril::{Atomicity, NonAtomic, Tendril};
+
+/// A `Tendril` which computes the hash of its contents once and caches it.
+///
+/// This is meant for tendrils used as `HashMap` keys, which would otherwise
+/// be rehashed in full on every lookup. The cached value is a 64-bit hash
+/// of the bytes, which is then fed to the map's own hasher.
+///
+/// Any access through `DerefMut`, including methods like `push_slice`,
+/// discards the cached value. `clone()` keeps it.
+///
+/// Because it does not hash the same way as its contents, a
+/// `HashedTendril` can only be looked up by another `HashedTendril`.
+pub struct HashedTendril<F, A = NonAtomic>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    tendril: Tendril<F, A>,
+    hash: Cell<Option<u64>>,
+}
+
+impl<F, A> HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Wrap a `Tendril`. Its hash is computed on first use.
+    #[inline]
+    pub fn new(tendril: Tendril<F, A>) -> HashedTendril<F, A> {
+        HashedTendril {
+            tendril,
+            hash: Cell::new(None),
+        }
+    }
+
+    /// Get the hash of the contents, computing it if necessary.
+    #[inline]
+    pub fn hash_value(&self) -> u64 {
+        match self.hash.get() {
+            Some(h) => h,
+            None => {
+                let mut hasher = DefaultHasher::new();
+                hasher.write(self.tendril.as_bytes());
+                let h = hasher.finish();
+                self.hash.set(Some(h));
+                h
+            }
+        }
+    }
+
+    /// Unwrap the `Tendril`.
+    #[inline]
+    pub fn into_inner(self) -> Tendril<F, A> {
+        self.tendril
+    }
+}
+
+impl<F, A> Deref for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Target = Tendril<F, A>;
+
+    #[inline]
+    fn deref(&self) -> &Tendril<F, A> {
+        &self.tendril
+    }
+}
+
+impl<F, A> DerefMut for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Tendril<F, A> {
+        self.hash.set(None);
+        &mut self.tendril
+    }
+}
+
+impl<F, A> Clone for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn clone(&self) -> HashedTendril<F, A> {
+        HashedTendril {
+            tendril: self.tendril.clone(),
+            hash: self.hash.clone(),
+        }
+    }
+}
+
+impl<F, A> From<Tendril<F, A>> for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn from(tendril: Tendril<F, A>) -> HashedTendril<F, A> {
+        HashedTendril::new(tendril)
+    }
+}
+
+impl<F, A> PartialEq for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        if let (Some(a), Some(b)) = (self.hash.get(), other.hash.get()) {
+            if a != b {
+                return false;
+            }
+        }
+        self.tendril == other.tendril
+    }
+}
+
+impl<F, A> Eq for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+}
+
+impl<F, A> Default for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn default() -> HashedTendril<F, A> {
+        HashedTendril::new(Tendril::new())
+    }
+}
+
+impl<F, A> strfmt::Debug for HashedTendril<F, A>
+where
+    F: fmt::SliceFormat + Default + strfmt::Debug,
+    <F as fmt::SliceFormat>::Slice: strfmt::Debug,
+    A: Atomicity,
+{
+    #[inline]
+    fn fmt(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        self.tendril.fmt(f)
+    }
+}
+
+impl<F, A> hash::Hash for HashedTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn hash<H: hash::Hasher>(&self, hasher: &mut H) {
+        hasher.write_u64(self.hash_value())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::HashedTendril;
+    use std::collections::HashMap;
+    use tendril::SliceExt;
+
+    #[test]
+    fn cache() {
+        let mut t = HashedTendril::new("some key".to_tendril());
+        assert_eq!(None, t.hash.get());
+        let h = t.hash_value();
+        assert_eq!(Some(h), t.hash.get());
+
+        let u = t.clone();
+        assert_eq!(Some(h), u.hash.get());
+
+        t.push_slice(" and more");
+        assert_eq!(None, t.hash.get());
+        assert!(t.hash_value() != h);
+        assert_eq!("some key and more", &**t);
+        assert!(t != u);
+
+        t.pop_back(9);
+        assert_eq!(h, t.hash_value());
+        assert!(t == u);
+    }
+
+    #[test]
+    fn map_key() {
+        let mut map = HashMap::new();
+        map.insert(HashedTendril::new("href".to_tendril()), 1);
+        map.insert(
+            HashedTendril::new("a longer attribute name".to_tendril()),
+            2,
+        );
+        let key = HashedTendril::new("a longer attribute name".to_tendril());
+        assert_eq!(Some(&2), map.get(&key));
+        assert_eq!(Some(&2), map.get(&key));
+        assert_eq!(None, map.get(&HashedTendril::new("src".to_tendril())));
+    }
+}
@@ -0,0 +1,407 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Interning tables which deduplicate tendril storage.
+
+use std::borrow::Borrow;
+use std::collections::HashSet;
+use std::hash;
+#[cfg(feature = "atomic")]
+use std::sync::Mutex;
+
+use fmt;
+#[cfg(feature = "atomic")]
+use tendril::Atomic;
+use tendril::{Atomicity, NonAtomic, Tendril, MAX_INLINE_LEN};
+
+/// A table entry which hashes and compares as its bytes, so that the
+/// table can be probed with a plain byte slice.
+struct Entry<F, A>(Tendril<F, A>)
+where
+    F: fmt::Format,
+    A: Atomicity;
+
+impl<F, A> Borrow<[u8]> for Entry<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn borrow(&self) -> &[u8] {
+        self.0.as_bytes()
+    }
+}
+
+impl<F, A> hash::Hash for Entry<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn hash<H: hash::Hasher>(&self, hasher: &mut H) {
+        self.0.hash(hasher)
+    }
+}
+
+impl<F, A> PartialEq for Entry<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<F, A> Eq for Entry<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+}
+
+/// Maps content to a canonical `Tendril`, so that repeated strings share
+/// one buffer.
+///
+/// Tendrils short enough to be stored inline are never shared, so they are
+/// passed through without being recorded.
+///
+/// A tendril which shares its buffer, such as a slice of a larger input, is
+/// copied before it becomes canonical. Each entry then owns its buffer, so
+/// it can be evicted once nothing outside the table refers to it.
+pub struct Interner<F, A = NonAtomic>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    set: HashSet<Entry<F, A>>,
+}
+
+impl<F, A> Interner<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Create an empty table.
+    #[inline]
+    pub fn new() -> Interner<F, A> {
+        Interner {
+            set: HashSet::new(),
+        }
+    }
+
+    /// Number of canonical tendrils in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.set.len()
+    }
+
+    /// Is the table empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.set.is_empty()
+    }
+
+    /// Return the canonical tendril with the same content as `t`.
+    ///
+    /// If there is none yet, `t` becomes canonical, or a copy of it if `t`
+    /// shares its buffer.
+    pub fn intern(&mut self, t: Tendril<F, A>) -> Tendril<F, A> {
+        if t.len32() <= MAX_INLINE_LEN as u32 {
+            return t;
+        }
+        if let Some(entry) = self.set.get::<[u8]>(t.as_bytes()) {
+            return entry.0.clone();
+        }
+
+        let t = if t.is_shared() {
+            unsafe { Tendril::from_byte_slice_without_validating(t.as_bytes()) }
+        } else {
+            t
+        };
+        let canonical = t.clone();
+        self.set.insert(Entry(t));
+        canonical
+    }
+
+    /// Look up the canonical tendril with the same content as `t`, without
+    /// inserting anything.
+    #[inline]
+    pub fn get(&self, t: &Tendril<F, A>) -> Option<Tendril<F, A>> {
+        self.set.get::<[u8]>(t.as_bytes()).map(|e| e.0.clone())
+    }
+
+    /// Does `t` share its buffer with the canonical tendril for its content?
+    #[inline]
+    pub fn is_canonical(&self, t: &Tendril<F, A>) -> bool {
+        match self.set.get::<[u8]>(t.as_bytes()) {
+            Some(entry) => entry.0.is_shared_with(t),
+            None => false,
+        }
+    }
+
+    /// Drop the canonical tendrils which nothing outside the table refers
+    /// to any more.
+    ///
+    /// Returns the number of entries removed.
+    pub fn evict_unused(&mut self) -> usize {
+        let before = self.set.len();
+        self.set.retain(|e| e.0.buffer_refcount() != Some(1));
+        before - self.set.len()
+    }
+
+    /// Remove every entry.
+    #[inline]
+    pub fn clear(&mut self) {
+        self.set.clear()
+    }
+}
+
+impl<F, A> Interner<F, A>
+where
+    F: fmt::SliceFormat,
+    A: Atomicity,
+{
+    /// Return the canonical tendril for a slice, copying the slice only if
+    /// there is none yet.
+    #[inline]
+    pub fn intern_slice(&mut self, x: &F::Slice) -> Tendril<F, A> {
+        use fmt::Slice;
+
+        if let Some(entry) = self.set.get::<[u8]>(x.as_bytes()) {
+            return entry.0.clone();
+        }
+        self.intern(Tendril::from_slice(x))
+    }
+}
+
+impl<F, A> Default for Interner<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn default() -> Interner<F, A> {
+        Interner::new()
+    }
+}
+
+/// An `Interner` which can be shared between threads.
+///
+/// The table is protected by a lock, and hands out `Atomic` tendrils.
+#[cfg(feature = "atomic")]
+pub struct ConcurrentInterner<F>
+where
+    F: fmt::Format,
+{
+    inner: Mutex<Interner<F, Atomic>>,
+}
+
+#[cfg(feature = "atomic")]
+impl<F> ConcurrentInterner<F>
+where
+    F: fmt::Format,
+{
+    /// Create an empty table.
+    #[inline]
+    pub fn new() -> ConcurrentInterner<F> {
+        ConcurrentInterner {
+            inner: Mutex::new(Interner::new()),
+        }
+    }
+
+    /// Number of canonical tendrils in the table.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().len()
+    }
+
+    /// Is the table empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.lock().unwrap().is_empty()
+    }
+
+    /// Return the canonical tendril with the same content as `t`.
+    ///
+    /// If there is none yet, `t` becomes canonical, or a copy of it if `t`
+    /// shares its buffer.
+    #[inline]
+    pub fn intern(&self, t: Tendril<F, Atomic>) -> Tendril<F, Atomic> {
+        self.inner.lock().unwrap().intern(t)
+    }
+
+    /// Look up the canonical tendril with the same content as `t`, without
+    /// inserting anything.
+    #[inline]
+    pub fn get(&self, t: &Tendril<F, Atomic>) -> Option<Tendril<F, Atomic>> {
+        self.inner.lock().unwrap().get(t)
+    }
+
+    /// Does `t` share its buffer with the canonical tendril for its content?
+    #[inline]
+    pub fn is_canonical(&self, t: &Tendril<F, Atomic>) -> bool {
+        self.inner.lock().unwrap().is_canonical(t)
+    }
+
+    /// Drop the canonical tendrils which nothing outside the table refers
+    /// to any more.
+    ///
+    /// Returns the number of entries removed.
+    #[inline]
+    pub fn evict_unused(&self) -> usize {
+        self.inner.lock().unwrap().evict_unused()
+    }
+
+    /// Remove every entry.
+    #[inline]
+    pub fn clear(&self) {
+        self.inner.lock().unwrap().clear()
+    }
+}
+
+#[cfg(feature = "atomic")]
+impl<F> ConcurrentInterner<F>
+where
+    F: fmt::SliceFormat,
+{
+    /// Return the canonical tendril for a slice, copying the slice only if
+    /// there is none yet.
+    #[inline]
+    pub fn intern_slice(&self, x: &F::Slice) -> Tendril<F, Atomic> {
+        self.inner.lock().unwrap().intern_slice(x)
+    }
+}
+
+#[cfg(feature = "atomic")]
+impl<F> Default for ConcurrentInterner<F>
+where
+    F: fmt::Format,
+{
+    #[inline]
+    fn default() -> ConcurrentInterner<F> {
+        ConcurrentInterner::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "atomic")]
+    use super::ConcurrentInterner;
+    use super::Interner;
+    use fmt;
+    #[cfg(feature = "atomic")]
+    use std::sync::Arc;
+    #[cfg(feature = "atomic")]
+    use std::thread;
+    use tendril::SliceExt;
+    #[cfg(feature = "atomic")]
+    use tendril::{Atomic, Tendril};
+
+    #[test]
+    fn intern() {
+        let mut interner = Interner::new();
+        let a = interner.intern("a long attribute value".to_tendril());
+        let b = interner.intern("a long attribute value".to_tendril());
+        assert_eq!(a, b);
+        assert!(a.is_shared_with(&b));
+        assert_eq!(1, interner.len());
+
+        let c = interner.intern_slice("a long attribute value");
+        assert!(c.is_shared_with(&a));
+        let d = interner.intern_slice("another long attribute value");
+        assert!(!d.is_shared_with(&a));
+        assert_eq!(2, interner.len());
+
+        assert!(interner.is_canonical(&a));
+        assert!(!interner.is_canonical(&"a long attribute value".to_tendril()));
+        assert!(interner
+            .get(&"a long attribute value".to_tendril())
+            .unwrap()
+            .is_shared_with(&a));
+        assert!(interner.get(&"not interned at all".to_tendril()).is_none());
+    }
+
+    #[test]
+    fn inline_passes_through() {
+        let mut interner: Interner<fmt::UTF8> = Interner::new();
+        let a = interner.intern("class".to_tendril());
+        assert_eq!("class", &*a);
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn subtendrils() {
+        let mut interner = Interner::new();
+        let input = "<a class=navigation-link><b class=navigation-link>".to_tendril();
+        let first = interner.intern(input.subtendril(9, 15));
+        let second = interner.intern(input.subtendril(34, 15));
+        assert_eq!("navigation-link", &*second);
+        assert!(second.is_shared_with(&first));
+        assert_eq!(1, interner.len());
+    }
+
+    #[test]
+    fn evict_unused() {
+        let mut interner: Interner<fmt::UTF8> = Interner::new();
+        let kept = interner.intern_slice("this one stays alive");
+        drop(interner.intern_slice("this one is dropped"));
+        assert_eq!(2, interner.len());
+
+        assert_eq!(1, interner.evict_unused());
+        assert_eq!(1, interner.len());
+        assert!(interner.is_canonical(&kept));
+
+        drop(kept);
+        assert_eq!(1, interner.evict_unused());
+        assert!(interner.is_empty());
+    }
+
+    #[test]
+    fn evict_slices_of_one_buffer() {
+        let mut interner: Interner<fmt::UTF8> = Interner::new();
+        let input = "<a href=first-long-value title=second-long-value>".to_tendril();
+        let first = interner.intern(input.subtendril(8, 16));
+        let second = interner.intern(input.subtendril(31, 17));
+        assert_eq!("first-long-value", &*first);
+        assert_eq!("second-long-value", &*second);
+        assert!(!first.is_shared_with(&input));
+        assert!(!first.is_shared_with(&second));
+
+        drop(first);
+        drop(second);
+        assert_eq!(2, interner.evict_unused());
+        assert!(interner.is_empty());
+    }
+
+    #[cfg(feature = "atomic")]
+    #[test]
+    fn concurrent() {
+        let interner: Arc<ConcurrentInterner<fmt::UTF8>> = Arc::new(ConcurrentInterner::new());
+        let threads: Vec<_> = (0..4)
+            .map(|_| {
+                let interner = interner.clone();
+                thread::spawn(move || {
+                    let t: Tendril<fmt::UTF8, Atomic> =
+                        Tendril::from_slice("shared between threads");
+                    let t = interner.intern(t);
+                    assert!(interner.is_canonical(&t));
+                    t
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads.into_iter().map(|t| t.join().unwrap()).collect();
+        assert_eq!(1, interner.len());
+        for t in &results {
+            assert!(t.is_shared_with(&results[0]));
+        }
+
+        assert_eq!(0, interner.evict_unused());
+        drop(results);
+        assert_eq!(1, interner.evict_unused());
+    }
+}
@@ -4,34 +4,126 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+#![cfg_attr(not(feature = "std"), no_std)]
 #![cfg_attr(all(test, feature = "bench"), feature(test))]
 //#![cfg_attr(test, deny(warnings))]
 
+// `#![no_std]` brings in `core` by itself.
+#[cfg(feature = "std")]
+extern crate core;
+
+extern crate alloc;
 #[macro_use]
 extern crate debug_unreachable;
 #[cfg(feature = "encoding")]
 pub extern crate encoding;
 #[cfg(feature = "encoding_rs")]
 pub extern crate encoding_rs;
+#[cfg(all(unix, feature = "mmap"))]
+extern crate libc;
+#[cfg(test)]
+extern crate rand;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_test;
 #[cfg(all(test, feature = "bench"))]
 extern crate test;
-#[macro_use]
-extern crate mac;
+#[cfg(feature = "unicode-normalization")]
+extern crate unicode_normalization;
+#[cfg(feature = "unicode-segmentation")]
+extern crate unicode_segmentation;
+#[cfg(feature = "std")]
 extern crate utf8;
 
+pub use byteset::ByteSet;
+#[cfg(feature = "std")]
+pub use cursor::TendrilCursor;
 pub use fmt::Format;
-pub use stream::TendrilSink;
-pub use tendril::{Atomic, Atomicity, NonAtomic, SendTendril};
-pub use tendril::{ByteTendril, ReadExt, SliceExt, StrTendril, SubtendrilError, Tendril};
+#[cfg(feature = "std")]
+pub use hashed::HashedTendril;
+#[cfg(all(feature = "std", feature = "atomic"))]
+pub use interner::ConcurrentInterner;
+#[cfg(feature = "std")]
+pub use interner::Interner;
+#[cfg(feature = "unicode-normalization")]
+pub use normalize::NormalizationForm;
+#[cfg(feature = "std")]
+pub use pool::TendrilPool;
+#[cfg(feature = "unicode-segmentation")]
+pub use segment::{Graphemes, Sentences, Words};
+#[cfg(feature = "std")]
+pub use snapshot::{SnapshotReader, SnapshotWriter};
+#[cfg(feature = "std")]
+pub use stream::{TendrilSink, TryTendrilSink};
+pub use transcode::{Transcode, TranscodeError};
+#[cfg(feature = "std")]
+pub use tendril::ReadExt;
+#[cfg(feature = "atomic")]
+pub use tendril::Atomic;
+pub use tendril::{Atomicity, NonAtomic, SendTendril, WeakTendril};
+pub use tendril::{ByteTendril, SliceExt, StrTendril, SubtendrilError, Tendril};
+#[cfg(feature = "std")]
 pub use utf8_decode::IncompleteUtf8;
+#[cfg(feature = "std")]
+pub use vectored::{write_all_vectored, VectoredWriter};
+pub use wtf8::{CodePoint, CodePoints};
+
+// These were once taken from the `mac` crate, which needs `std`.
+macro_rules! unwrap_or_return {
+    ($e:expr, $r:expr) => {
+        match $e {
+            Some(e) => e,
+            None => return $r,
+        }
+    };
+}
+
+macro_rules! matches {
+    ($e:expr, $($pat:tt)+) => {
+        match $e {
+            $($pat)+ => true,
+            _ => false,
+        }
+    };
+}
 
 pub mod fmt;
+#[cfg(feature = "std")]
 pub mod stream;
 
 mod buf32;
+mod byteset;
+mod case;
+#[cfg(feature = "std")]
+mod cursor;
 mod futf;
+#[cfg(feature = "std")]
+mod hashed;
+#[cfg(feature = "std")]
+mod interner;
+#[cfg(all(unix, feature = "mmap"))]
+mod mmap;
+#[cfg(feature = "unicode-normalization")]
+mod normalize;
+#[cfg(all(feature = "std", any(unix, windows)))]
+mod os;
+#[cfg(feature = "std")]
+mod pool;
+#[cfg(feature = "unicode-segmentation")]
+mod segment;
+#[cfg(feature = "serde")]
+mod serialize;
+#[cfg(feature = "std")]
+mod snapshot;
 mod tendril;
+mod transcode;
+#[cfg(feature = "std")]
 mod utf8_decode;
+mod validate;
 mod util;
+#[cfg(feature = "std")]
+mod vectored;
+mod wtf8;
 
 static OFLOW: &'static str = "tendril: overflow in buffer arithmetic";
@@ -0,0 +1,220 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Tendrils over memory-mapped files, with the `mmap` feature on Unix.
+//!
+//! We reserve one page more than the file needs, and map the file over
+//! all but the first page. The header goes at the end of that first page,
+//! so that the file contents follow it just like a heap buffer. A mapped
+//! `Tendril` is always shared, which makes any mutation copy it first.
+
+use std::fs::File;
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+use std::{io, mem, ptr};
+
+use libc;
+
+use buf32::Buf32;
+use fmt;
+use tendril::{Atomicity, Header, Tendril};
+
+#[inline]
+fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Unmap a buffer made by `map`, once nothing refers to it.
+#[inline]
+pub unsafe fn release<A>(buf: Buf32<Header<A>>)
+where
+    A: Atomicity,
+{
+    let page = page_size();
+    let base = buf.ptr as usize + mem::size_of::<Header<A>>() - page;
+    libc::munmap(base as *mut libc::c_void, page + buf.cap as usize);
+}
+
+/// Map a file, returning the address for its header and its length.
+unsafe fn map<A>(file: &File) -> io::Result<(*mut Header<A>, u32)>
+where
+    A: Atomicity,
+{
+    let len = file.metadata()?.len();
+    if len >> 32 != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "tendril: file too large to map",
+        ));
+    }
+    let len = len as usize;
+    let page = page_size();
+
+    let base = libc::mmap(
+        ptr::null_mut(),
+        page + len,
+        libc::PROT_READ | libc::PROT_WRITE,
+        libc::MAP_PRIVATE | libc::MAP_ANON,
+        -1,
+        0,
+    );
+    if base == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    let data = (base as usize + page) as *mut libc::c_void;
+    let mapped = libc::mmap(
+        data,
+        len,
+        libc::PROT_READ,
+        libc::MAP_PRIVATE | libc::MAP_FIXED,
+        file.as_raw_fd(),
+        0,
+    );
+    if mapped == libc::MAP_FAILED {
+        let err = io::Error::last_os_error();
+        libc::munmap(base, page + len);
+        return Err(err);
+    }
+
+    let header = (data as usize - mem::size_of::<Header<A>>()) as *mut Header<A>;
+    Ok((header, len as u32))
+}
+
+impl<A> Tendril<fmt::Bytes, A>
+where
+    A: Atomicity,
+{
+    /// Map a file into memory and use it as a `Tendril`, without copying.
+    ///
+    /// Subtendrils keep the mapping alive, and it's unmapped when the last
+    /// of them is dropped. Mutation copies the contents to the heap first.
+    /// Files larger than 4 GB can't be mapped.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, by
+    /// this process or any other. Either may change or invalidate the
+    /// contents of the `Tendril`.
+    pub unsafe fn map_file<P>(path: P) -> io::Result<Tendril<fmt::Bytes, A>>
+    where
+        P: AsRef<Path>,
+    {
+        let file = File::open(path)?;
+        if file.metadata()?.len() == 0 {
+            // Empty mappings aren't allowed.
+            return Ok(Tendril::new());
+        }
+        let (header, len) = map(&file)?;
+        Ok(Tendril::mapped(header, len))
+    }
+}
+
+impl<A> Tendril<fmt::UTF8, A>
+where
+    A: Atomicity,
+{
+    /// Map a UTF-8 file into memory and use it as a `Tendril`, without
+    /// copying. The contents are validated once, here.
+    ///
+    /// This is `map_file`, which see, followed by a UTF-8 check.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, by
+    /// this process or any other.
+    pub unsafe fn map_file_utf8<P>(path: P) -> io::Result<Tendril<fmt::UTF8, A>>
+    where
+        P: AsRef<Path>,
+    {
+        Tendril::<fmt::Bytes, A>::map_file(path)?
+            .try_reinterpret()
+            .map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "stream did not contain valid UTF-8",
+                )
+            })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+    use std::process;
+    #[cfg(feature = "atomic")]
+    use std::thread;
+
+    #[cfg(feature = "atomic")]
+    use fmt;
+    #[cfg(feature = "atomic")]
+    use tendril::Atomic;
+    use tendril::{ByteTendril, StrTendril, Tendril};
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = env::temp_dir().join(format!("tendril-mmap-{}-{}", process::id(), name));
+        File::create(&path).unwrap().write_all(contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn map_file() {
+        let path = temp_file("bytes", b"some bytes \xff in a mapped file");
+        let t = unsafe { ByteTendril::map_file(&path).unwrap() };
+        fs::remove_file(&path).unwrap();
+        assert_eq!(b"some bytes \xff in a mapped file", &*t);
+        assert!(t.is_shared());
+
+        let sub = t.subtendril(13, 9);
+        drop(t);
+        assert_eq!(b"in a mapp", &*sub);
+
+        let mut owned = sub.clone();
+        owned.push_slice(b"ed");
+        assert!(!owned.is_shared());
+        assert_eq!(b"in a mapped", &*owned);
+        assert_eq!(b"in a mapp", &*sub);
+    }
+
+    #[test]
+    fn map_file_utf8() {
+        let path = temp_file("utf8", "mapped text, caf\u{e9} and all".as_bytes());
+        let t: StrTendril = unsafe { Tendril::map_file_utf8(&path).unwrap() };
+        assert_eq!("mapped text, caf\u{e9} and all", &*t);
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_file("invalid", b"not \xff UTF-8");
+        let err = unsafe { StrTendril::map_file_utf8(&path).unwrap_err() };
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn empty_and_missing() {
+        let path = temp_file("empty", b"");
+        let t = unsafe { ByteTendril::map_file(&path).unwrap() };
+        assert!(t.is_empty());
+        fs::remove_file(&path).unwrap();
+        assert!(unsafe { ByteTendril::map_file(&path) }.is_err());
+    }
+
+    #[cfg(feature = "atomic")]
+    #[test]
+    fn atomic() {
+        let path = temp_file("atomic", b"shared between threads, mapped once");
+        let t: Tendril<fmt::Bytes, Atomic> = unsafe { Tendril::map_file(&path).unwrap() };
+        fs::remove_file(&path).unwrap();
+        let u = t.subtendril(0, 20);
+        let weak = t.downgrade();
+        thread::spawn(move || assert_eq!(b"shared between threa", &*u))
+            .join()
+            .unwrap();
+        drop(t);
+        assert!(weak.upgrade().is_none());
+    }
+}
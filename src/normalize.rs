@@ -0,0 +1,159 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Unicode normalization, with the `unicode-normalization` feature.
+//!
+//! Each conversion returns a clone of the input, sharing its buffer, when
+//! the text is already normalized.
+
+use alloc::string::String;
+#[cfg(feature = "std")]
+use core::iter;
+
+#[cfg(feature = "std")]
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::{self as un, IsNormalized, UnicodeNormalization};
+
+use fmt;
+use tendril::{Atomicity, Tendril};
+
+/// One of the four Unicode normalization forms.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum NormalizationForm {
+    /// Canonical decomposition, followed by canonical composition.
+    NFC,
+    /// Canonical decomposition.
+    NFD,
+    /// Compatibility decomposition, followed by canonical composition.
+    NFKC,
+    /// Compatibility decomposition.
+    NFKD,
+}
+
+impl NormalizationForm {
+    #[inline]
+    fn quick_check<I>(self, chars: I) -> IsNormalized
+    where
+        I: Iterator<Item = char>,
+    {
+        match self {
+            NormalizationForm::NFC => un::is_nfc_quick(chars),
+            NormalizationForm::NFD => un::is_nfd_quick(chars),
+            NormalizationForm::NFKC => un::is_nfkc_quick(chars),
+            NormalizationForm::NFKD => un::is_nfkd_quick(chars),
+        }
+    }
+
+    fn to_string(self, s: &str) -> String {
+        match self {
+            NormalizationForm::NFC => s.nfc().collect(),
+            NormalizationForm::NFD => s.nfd().collect(),
+            NormalizationForm::NFKC => s.nfkc().collect(),
+            NormalizationForm::NFKD => s.nfkd().collect(),
+        }
+    }
+
+    /// Can text be split before `c` and each side normalized on its own?
+    ///
+    /// True for starters which are unaffected by this form, so nothing
+    /// before them can reorder or compose with what follows.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn is_boundary(self, c: char) -> bool {
+        canonical_combining_class(c) == 0 && self.quick_check(iter::once(c)) == IsNormalized::Yes
+    }
+
+    /// Normalize a tendril, sharing its buffer if it's already normalized.
+    pub(crate) fn normalize<A>(self, t: &Tendril<fmt::UTF8, A>) -> Tendril<fmt::UTF8, A>
+    where
+        A: Atomicity,
+    {
+        match self.quick_check(t.chars()) {
+            IsNormalized::Yes => t.clone(),
+            IsNormalized::No => Tendril::from(self.to_string(t)),
+            IsNormalized::Maybe => {
+                let s = self.to_string(t);
+                if s == **t {
+                    t.clone()
+                } else {
+                    Tendril::from(s)
+                }
+            }
+        }
+    }
+}
+
+impl<A> Tendril<fmt::UTF8, A>
+where
+    A: Atomicity,
+{
+    /// Convert to Normalization Form C.
+    #[inline]
+    pub fn nfc(&self) -> Tendril<fmt::UTF8, A> {
+        NormalizationForm::NFC.normalize(self)
+    }
+
+    /// Convert to Normalization Form D.
+    #[inline]
+    pub fn nfd(&self) -> Tendril<fmt::UTF8, A> {
+        NormalizationForm::NFD.normalize(self)
+    }
+
+    /// Convert to Normalization Form KC.
+    #[inline]
+    pub fn nfkc(&self) -> Tendril<fmt::UTF8, A> {
+        NormalizationForm::NFKC.normalize(self)
+    }
+
+    /// Convert to Normalization Form KD.
+    #[inline]
+    pub fn nfkd(&self) -> Tendril<fmt::UTF8, A> {
+        NormalizationForm::NFKD.normalize(self)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "std")]
+    use super::NormalizationForm;
+    use tendril::SliceExt;
+
+    #[test]
+    fn forms() {
+        let t = "e\u{301}cole, \u{fb01}n de l'ann\u{e9}e".to_tendril();
+        assert_eq!("\u{e9}cole, \u{fb01}n de l'ann\u{e9}e", &*t.nfc());
+        assert_eq!("e\u{301}cole, \u{fb01}n de l'anne\u{301}e", &*t.nfd());
+        assert_eq!("\u{e9}cole, fin de l'ann\u{e9}e", &*t.nfkc());
+        assert_eq!("e\u{301}cole, fin de l'anne\u{301}e", &*t.nfkd());
+    }
+
+    #[test]
+    fn shared_when_normalized() {
+        let t = "plain ASCII is in every form".to_tendril();
+        assert!(t.nfc().is_shared_with(&t));
+        assert!(t.nfd().is_shared_with(&t));
+        assert!(t.nfkc().is_shared_with(&t));
+        assert!(t.nfkd().is_shared_with(&t));
+
+        // U+0301 is "maybe" for the NFC quick check, so these take the
+        // slow path.
+        let t = "a\u{301} and \u{1100}\u{1161} do compose".to_tendril();
+        assert_eq!("\u{e1} and \u{ac00} do compose", &*t.nfc());
+        let t = "q\u{301} has no precomposed form".to_tendril();
+        assert!(t.nfc().is_shared_with(&t));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn boundary() {
+        assert!(NormalizationForm::NFC.is_boundary('a'));
+        assert!(!NormalizationForm::NFC.is_boundary('\u{301}'));
+        // A Hangul vowel can compose with a preceding leading consonant.
+        assert!(!NormalizationForm::NFC.is_boundary('\u{1161}'));
+        assert!(NormalizationForm::NFD.is_boundary('\u{1161}'));
+        assert!(!NormalizationForm::NFD.is_boundary('\u{e9}'));
+    }
+}
@@ -0,0 +1,135 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversions between tendrils and platform strings.
+//!
+//! On Unix an `OsStr` is arbitrary bytes, so it maps onto a `ByteTendril`.
+//! On Windows it is potentially ill-formed UTF-16, which maps onto a WTF-8
+//! tendril without loss.
+
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+
+use fmt;
+use tendril::{Atomicity, Tendril};
+
+#[cfg(unix)]
+impl<A> Tendril<fmt::Bytes, A>
+where
+    A: Atomicity,
+{
+    /// Build a `Tendril` by copying the bytes of an `OsStr`.
+    #[inline]
+    pub fn from_os_str(s: &OsStr) -> Tendril<fmt::Bytes, A> {
+        use std::os::unix::ffi::OsStrExt;
+
+        Tendril::from_slice(s.as_bytes())
+    }
+
+    /// Build a `Tendril` by copying the bytes of a `Path`.
+    #[inline]
+    pub fn from_path(p: &Path) -> Tendril<fmt::Bytes, A> {
+        Tendril::from_os_str(p.as_os_str())
+    }
+
+    /// View the bytes as an `OsStr`.
+    #[inline]
+    pub fn as_os_str(&self) -> &OsStr {
+        use std::os::unix::ffi::OsStrExt;
+
+        OsStr::from_bytes(self)
+    }
+
+    /// Copy the bytes into an `OsString`.
+    #[inline]
+    pub fn to_os_string(&self) -> OsString {
+        self.as_os_str().to_os_string()
+    }
+
+    /// Copy the bytes into a `PathBuf`.
+    #[inline]
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf::from(self.to_os_string())
+    }
+}
+
+#[cfg(windows)]
+impl<A> Tendril<fmt::WTF8, A>
+where
+    A: Atomicity,
+{
+    /// Build a `Tendril` from an `OsStr`, keeping any unpaired surrogates.
+    #[inline]
+    pub fn from_os_str(s: &OsStr) -> Tendril<fmt::WTF8, A> {
+        use std::os::windows::ffi::OsStrExt;
+
+        let units: Vec<u16> = s.encode_wide().collect();
+        Tendril::from_wide(&units)
+    }
+
+    /// Build a `Tendril` from a `Path`, keeping any unpaired surrogates.
+    #[inline]
+    pub fn from_path(p: &Path) -> Tendril<fmt::WTF8, A> {
+        Tendril::from_os_str(p.as_os_str())
+    }
+
+    /// Convert to an `OsString`.
+    #[inline]
+    pub fn to_os_string(&self) -> OsString {
+        use std::os::windows::ffi::OsStringExt;
+
+        OsString::from_wide(&self.to_wide())
+    }
+
+    /// Convert to a `PathBuf`.
+    #[inline]
+    pub fn to_path_buf(&self) -> PathBuf {
+        PathBuf::from(self.to_os_string())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod test {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::Path;
+    use tendril::ByteTendril;
+
+    #[test]
+    fn os_str() {
+        let s = OsStr::from_bytes(b"not \xFF UTF-8");
+        let t = ByteTendril::from_os_str(s);
+        assert_eq!(b"not \xFF UTF-8", &*t);
+        assert_eq!(s, t.as_os_str());
+        assert_eq!(s, &*t.to_os_string());
+
+        let p = Path::new("/some/long/path/to/a/file.txt");
+        let t = ByteTendril::from_path(p);
+        assert_eq!(p, &*t.to_path_buf());
+    }
+}
+
+#[cfg(all(test, windows))]
+mod test {
+    use fmt;
+    use std::ffi::OsString;
+    use std::os::windows::ffi::OsStringExt;
+    use std::path::Path;
+    use tendril::Tendril;
+
+    #[test]
+    fn os_str() {
+        // An unpaired surrogate, which isn't valid UTF-16.
+        let s = OsString::from_wide(&[0x61, 0xD800, 0x62]);
+        let t: Tendril<fmt::WTF8> = Tendril::from_os_str(&s);
+        assert_eq!(b"a\xED\xA0\x80b", &**t.as_bytes());
+        assert_eq!(s, t.to_os_string());
+
+        let p = Path::new(r"C:\some\long\path\to\a\file.txt");
+        let t: Tendril<fmt::WTF8> = Tendril::from_path(p);
+        assert_eq!(p, &*t.to_path_buf());
+    }
+}
@@ -0,0 +1,253 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! A pool of reusable tendril buffers.
+//!
+//! A pooled buffer is allocated with one extra header-sized slot in front
+//! of the header, holding a strong reference to the pool. When the last
+//! tendril using the buffer goes away, the buffer is put back on the
+//! pool's idle list rather than freed.
+
+use std::sync::{Arc, Mutex};
+use std::{cmp, mem, ptr};
+
+use buf32::{self, Buf32};
+use fmt;
+use tendril::{Atomicity, Header, NonAtomic, Tendril};
+
+type H = Header<NonAtomic>;
+
+struct Shared {
+    buffer_size: u32,
+    max_idle: usize,
+    idle: Mutex<Vec<usize>>,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        let idle = self.idle.get_mut().unwrap();
+        for header in idle.drain(..) {
+            unsafe {
+                dealloc(header as *mut H, self.buffer_size);
+            }
+        }
+    }
+}
+
+#[inline]
+unsafe fn alloc(cap: u32) -> *mut H {
+    let mut vec = Vec::<H>::with_capacity(1 + buf32::bytes_to_vec_capacity::<H>(cap));
+    let ptr = vec.as_mut_ptr();
+    mem::forget(vec);
+    ptr.offset(1)
+}
+
+#[inline]
+unsafe fn dealloc<T>(header: *mut T, cap: u32) {
+    mem::drop(Vec::from_raw_parts(
+        header.offset(-1),
+        0,
+        1 + buf32::bytes_to_vec_capacity::<T>(cap),
+    ));
+}
+
+/// Give a pooled buffer back to its pool, once nothing refers to it.
+#[inline]
+pub unsafe fn release<A>(buf: Buf32<Header<A>>)
+where
+    A: Atomicity,
+{
+    let slot = buf.ptr.offset(-1) as *mut *const Shared;
+    let shared = Arc::from_raw(ptr::read(slot));
+    {
+        let mut idle = shared.idle.lock().unwrap();
+        if buf.cap == shared.buffer_size && idle.len() < shared.max_idle {
+            idle.push(buf.ptr as usize);
+            return;
+        }
+    }
+    dealloc(buf.ptr, buf.cap);
+}
+
+/// A source of pre-sized buffers which are recycled when the last `Tendril`
+/// using them is dropped.
+///
+/// Cloning a `TendrilPool` gives another handle to the same pool. Buffers
+/// keep the pool alive, so tendrils may outlive every handle.
+///
+/// A pooled `Tendril` behaves like any other. If it needs to grow beyond
+/// the pool's buffer size, it moves to a heap buffer of its own and the
+/// pool buffer is given back.
+#[derive(Clone)]
+pub struct TendrilPool {
+    shared: Arc<Shared>,
+}
+
+impl TendrilPool {
+    /// Create a pool handing out buffers of `buffer_size` bytes, which
+    /// keeps at most 64 idle buffers around.
+    #[inline]
+    pub fn new(buffer_size: u32) -> TendrilPool {
+        TendrilPool::with_max_idle(buffer_size, 64)
+    }
+
+    /// Create a pool handing out buffers of `buffer_size` bytes, which
+    /// keeps at most `max_idle` idle buffers around.
+    ///
+    /// Buffers are never smaller than 16 bytes.
+    #[inline]
+    pub fn with_max_idle(buffer_size: u32, max_idle: usize) -> TendrilPool {
+        TendrilPool {
+            shared: Arc::new(Shared {
+                buffer_size: cmp::max(buffer_size, buf32::MIN_CAP),
+                max_idle,
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    /// Get the size of the buffers in this pool.
+    #[inline]
+    pub fn buffer_size(&self) -> u32 {
+        self.shared.buffer_size
+    }
+
+    /// Get the number of buffers waiting to be reused.
+    #[inline]
+    pub fn idle(&self) -> usize {
+        self.shared.idle.lock().unwrap().len()
+    }
+
+    /// Get an empty `Tendril` whose capacity is the pool's buffer size.
+    pub fn get<F, A>(&self) -> Tendril<F, A>
+    where
+        F: fmt::Format,
+        A: Atomicity,
+    {
+        let reused = self.shared.idle.lock().unwrap().pop();
+        let cap = self.shared.buffer_size;
+        unsafe {
+            let header = match reused {
+                Some(p) => p as *mut H,
+                None => alloc(cap),
+            };
+            let slot = header.offset(-1) as *mut *const Shared;
+            ptr::write(slot, Arc::into_raw(self.shared.clone()));
+            Tendril::pooled(header as *mut Header<A>, cap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::TendrilPool;
+    use buf32;
+    #[cfg(feature = "atomic")]
+    use fmt;
+    #[cfg(feature = "atomic")]
+    use std::thread;
+    #[cfg(feature = "atomic")]
+    use tendril::{Atomic, Tendril};
+    use tendril::{ByteTendril, ReadExt, StrTendril};
+
+    #[test]
+    fn reuse() {
+        let pool = TendrilPool::new(100);
+        assert_eq!(100, pool.buffer_size());
+        assert_eq!(0, pool.idle());
+
+        let mut t: StrTendril = pool.get();
+        assert_eq!(100, t.capacity());
+        t.push_slice("a string long enough for the heap");
+        let p = t.as_ptr();
+        drop(t);
+        assert_eq!(1, pool.idle());
+
+        let mut t: StrTendril = pool.get();
+        assert_eq!(0, pool.idle());
+        assert_eq!("", &*t);
+        t.push_slice("another string for the heap");
+        assert_eq!(p, t.as_ptr());
+    }
+
+    #[test]
+    fn shared() {
+        let pool = TendrilPool::new(100);
+        let mut t: StrTendril = pool.get();
+        t.push_slice("a string long enough for the heap");
+        let a = t.subtendril(0, 10);
+        let b = t.subtendril(10, 20);
+        drop(t);
+        drop(a);
+        assert_eq!(0, pool.idle());
+        assert_eq!("ong enough for the h", &*b);
+        drop(b);
+        assert_eq!(1, pool.idle());
+    }
+
+    #[test]
+    fn outgrow() {
+        let pool = TendrilPool::new(16);
+        let mut t: StrTendril = pool.get();
+        t.push_slice("0123456789");
+        t.push_slice("0123456789");
+        assert_eq!(1, pool.idle());
+        assert_eq!("01234567890123456789", &*t);
+        drop(t);
+        assert_eq!(1, pool.idle());
+
+        let mut t: StrTendril = pool.get();
+        t.push_slice("0123456789");
+        t.shrink_to_fit();
+        assert_eq!(1, pool.idle());
+        assert_eq!(buf32::MIN_CAP, t.capacity());
+    }
+
+    #[test]
+    fn max_idle() {
+        let pool = TendrilPool::with_max_idle(32, 2);
+        let ts: Vec<ByteTendril> = (0..5).map(|_| pool.get()).collect();
+        drop(ts);
+        assert_eq!(2, pool.idle());
+    }
+
+    #[cfg(feature = "atomic")]
+    #[test]
+    fn outlive_pool() {
+        let pool = TendrilPool::new(64);
+        let mut t: StrTendril = pool.get();
+        drop(pool.get::<fmt::UTF8, Atomic>());
+        drop(pool);
+        t.push_slice("still usable after the pool is gone");
+        assert_eq!("still usable after the pool is gone", &*t);
+    }
+
+    #[cfg(feature = "atomic")]
+    #[test]
+    fn atomic() {
+        let pool = TendrilPool::new(64);
+        let mut t: Tendril<fmt::UTF8, Atomic> = pool.get();
+        t.push_slice("sent to another thread");
+        let u = t.clone();
+        thread::spawn(move || assert_eq!("sent to another thread", &*u))
+            .join()
+            .unwrap();
+        drop(t);
+        assert_eq!(1, pool.idle());
+    }
+
+    #[test]
+    fn read_to_tendril_pooled() {
+        let pool = TendrilPool::new(4096);
+        let mut t = ByteTendril::new();
+        let mut input: &[u8] = b"some bytes to read into a pooled buffer";
+        assert_eq!(39, input.read_to_tendril_pooled(&mut t, &pool).unwrap());
+        assert_eq!(b"some bytes to read into a pooled buffer", &*t);
+        assert_eq!(4096, t.capacity());
+        drop(t);
+        assert_eq!(1, pool.idle());
+    }
+}
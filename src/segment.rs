@@ -0,0 +1,222 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Text segmentation by the rules of UAX #29, with the
+//! `unicode-segmentation` feature.
+//!
+//! Each segment is a subtendril sharing the buffer of the original, unless
+//! it is short enough to be stored inline.
+
+use unicode_segmentation::{
+    GraphemeIndices, USentenceBoundIndices, UWordBoundIndices, UnicodeSegmentation,
+};
+
+use fmt;
+use tendril::{Atomicity, Tendril};
+
+macro_rules! segment_iter {
+    ($(#[$attr:meta])* $name:ident, $inner:ident) => {
+        $(#[$attr])*
+        pub struct $name<'a, A>
+        where
+            A: Atomicity + 'a,
+        {
+            tendril: &'a Tendril<fmt::UTF8, A>,
+            inner: $inner<'a>,
+        }
+
+        impl<'a, A> $name<'a, A>
+        where
+            A: Atomicity,
+        {
+            #[inline]
+            fn segment(&self, idx: usize, s: &str) -> Tendril<fmt::UTF8, A> {
+                unsafe { self.tendril.unsafe_subtendril(idx as u32, s.len() as u32) }
+            }
+        }
+    };
+}
+
+segment_iter! {
+    /// Iterator over the extended grapheme clusters of a `StrTendril`.
+    ///
+    /// Made by `Tendril::graphemes`.
+    Graphemes, GraphemeIndices
+}
+
+segment_iter! {
+    /// Iterator over the words of a `StrTendril`.
+    ///
+    /// Made by `Tendril::words`.
+    Words, UWordBoundIndices
+}
+
+segment_iter! {
+    /// Iterator over the sentences of a `StrTendril`.
+    ///
+    /// Made by `Tendril::sentences`.
+    Sentences, USentenceBoundIndices
+}
+
+impl<'a, A> Iterator for Graphemes<'a, A>
+where
+    A: Atomicity,
+{
+    type Item = Tendril<fmt::UTF8, A>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Tendril<fmt::UTF8, A>> {
+        let (idx, s) = self.inner.next()?;
+        Some(self.segment(idx, s))
+    }
+}
+
+impl<'a, A> Iterator for Words<'a, A>
+where
+    A: Atomicity,
+{
+    type Item = Tendril<fmt::UTF8, A>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Tendril<fmt::UTF8, A>> {
+        loop {
+            let (idx, s) = self.inner.next()?;
+            if s.chars().any(char::is_alphanumeric) {
+                return Some(self.segment(idx, s));
+            }
+        }
+    }
+}
+
+impl<'a, A> Iterator for Sentences<'a, A>
+where
+    A: Atomicity,
+{
+    type Item = Tendril<fmt::UTF8, A>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Tendril<fmt::UTF8, A>> {
+        let (idx, s) = self.inner.next()?;
+        Some(self.segment(idx, s))
+    }
+}
+
+impl<A> Tendril<fmt::UTF8, A>
+where
+    A: Atomicity,
+{
+    /// Iterate over the extended grapheme clusters.
+    #[inline]
+    pub fn graphemes<'a>(&'a self) -> Graphemes<'a, A> {
+        Graphemes {
+            tendril: self,
+            inner: self.as_ref().grapheme_indices(true),
+        }
+    }
+
+    /// Iterate over the words, skipping the segments between them which
+    /// have no letters or numbers, such as spaces and punctuation.
+    #[inline]
+    pub fn words<'a>(&'a self) -> Words<'a, A> {
+        Words {
+            tendril: self,
+            inner: self.as_ref().split_word_bound_indices(),
+        }
+    }
+
+    /// Iterate over the sentences. Each one includes the spaces after it.
+    #[inline]
+    pub fn sentences<'a>(&'a self) -> Sentences<'a, A> {
+        Sentences {
+            tendril: self,
+            inner: self.as_ref().split_sentence_bound_indices(),
+        }
+    }
+
+    /// Remove and return the first extended grapheme cluster, if any.
+    #[inline]
+    pub fn pop_front_grapheme(&mut self) -> Option<Tendril<fmt::UTF8, A>> {
+        let len = self.as_ref().graphemes(true).next()?.len() as u32;
+        if len == self.len32() {
+            let t = self.clone();
+            self.clear();
+            return Some(t);
+        }
+        unsafe {
+            let t = self.unsafe_subtendril(0, len);
+            self.unsafe_pop_front(len);
+            Some(t)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use alloc::{vec, vec::Vec};
+    use tendril::SliceExt;
+
+    #[test]
+    fn graphemes() {
+        let t = "e\u{301}\u{1f1eb}\u{1f1f7}\r\n\u{1f469}\u{200d}\u{1f4bb}!".to_tendril();
+        let gs: Vec<_> = t.graphemes().collect();
+        let strs: Vec<&str> = gs.iter().map(|g| &**g).collect();
+        assert_eq!(
+            vec![
+                "e\u{301}",
+                "\u{1f1eb}\u{1f1f7}",
+                "\r\n",
+                "\u{1f469}\u{200d}\u{1f4bb}",
+                "!"
+            ],
+            strs
+        );
+        assert!(gs[3].is_shared_with(&t.subtendril(13, 11)));
+    }
+
+    #[test]
+    fn words_and_sentences() {
+        let t = "Mr. O'Neil's 2nd try, at 3.5 km. Unbelievable!".to_tendril();
+        let words: Vec<_> = t.words().collect();
+        assert_eq!(
+            vec![
+                "Mr",
+                "O'Neil's",
+                "2nd",
+                "try",
+                "at",
+                "3.5",
+                "km",
+                "Unbelievable"
+            ],
+            words.iter().map(|w| &**w).collect::<Vec<_>>()
+        );
+        assert!(words[7].is_shared_with(&t.subtendril(33, 12)));
+
+        let t = "First one. Second one? Third".to_tendril();
+        let sentences: Vec<_> = t.sentences().collect();
+        assert_eq!(
+            vec!["First one. ", "Second one? ", "Third"],
+            sentences.iter().map(|s| &**s).collect::<Vec<_>>()
+        );
+        assert!(sentences[1].is_shared_with(&t.subtendril(11, 12)));
+    }
+
+    #[test]
+    fn pop_front_grapheme() {
+        let mut t = "a\u{308}o\u{308}u\u{308} with umlauts".to_tendril();
+        assert_eq!("a\u{308}", &*t.pop_front_grapheme().unwrap());
+        assert_eq!("o\u{308}", &*t.pop_front_grapheme().unwrap());
+        assert_eq!("u\u{308} with umlauts", &*t);
+
+        let mut t = "\u{1f469}\u{200d}\u{1f4bb}".to_tendril();
+        assert_eq!(
+            "\u{1f469}\u{200d}\u{1f4bb}",
+            &*t.pop_front_grapheme().unwrap()
+        );
+        assert!(t.is_empty());
+        assert!(t.pop_front_grapheme().is_none());
+    }
+}
@@ -0,0 +1,319 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Serde support, with the `serde` feature.
+//!
+//! Text formats serialize as strings, and the others as bytes. Input is
+//! validated as it is copied into the new `Tendril`.
+
+use alloc::string::String;
+use core::fmt as strfmt;
+use core::marker::PhantomData;
+use core::str;
+
+use serde::de::{self, Deserialize, Deserializer, SeqAccess, Unexpected, Visitor};
+use serde::ser::{Serialize, Serializer};
+
+use fmt;
+use tendril::{Atomicity, Tendril};
+
+/// Visits a string, or the bytes of one.
+struct StrVisitor<F, A> {
+    expected: &'static str,
+    marker: PhantomData<(F, A)>,
+}
+
+impl<'de, F, A> Visitor<'de> for StrVisitor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Value = Tendril<F, A>;
+
+    fn expecting(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        f.write_str(self.expected)
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Tendril<F, A>, E>
+    where
+        E: de::Error,
+    {
+        Tendril::try_from_byte_slice(v.as_bytes())
+            .map_err(|()| E::invalid_value(Unexpected::Str(v), &self))
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Tendril<F, A>, E>
+    where
+        E: de::Error,
+    {
+        Tendril::try_from_byte_slice(v).map_err(|()| E::invalid_value(Unexpected::Bytes(v), &self))
+    }
+}
+
+/// Visits bytes, or a sequence of them for formats like JSON.
+struct BytesVisitor<F, A> {
+    expected: &'static str,
+    marker: PhantomData<(F, A)>,
+}
+
+impl<'de, F, A> Visitor<'de> for BytesVisitor<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Value = Tendril<F, A>;
+
+    fn expecting(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        f.write_str(self.expected)
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Tendril<F, A>, E>
+    where
+        E: de::Error,
+    {
+        Tendril::try_from_byte_slice(v).map_err(|()| E::invalid_value(Unexpected::Bytes(v), &self))
+    }
+
+    fn visit_seq<S>(self, mut seq: S) -> Result<Tendril<F, A>, S::Error>
+    where
+        S: SeqAccess<'de>,
+    {
+        let mut buf = Tendril::<fmt::Bytes, A>::new();
+        while let Some(b) = seq.next_element::<u8>()? {
+            buf.push_slice(&[b]);
+        }
+        buf.try_reinterpret()
+            .map_err(|buf| de::Error::invalid_value(Unexpected::Bytes(&buf), &self))
+    }
+}
+
+macro_rules! serde_str_formats {
+    ($($fmt:ident: $expected:expr;)*) => {$(
+        impl<A> Serialize for Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                // Both formats are valid UTF-8.
+                serializer.serialize_str(unsafe { str::from_utf8_unchecked(self.as_bytes()) })
+            }
+        }
+
+        impl<'de, A> Deserialize<'de> for Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_str(StrVisitor {
+                    expected: $expected,
+                    marker: PhantomData,
+                })
+            }
+        }
+    )*};
+}
+
+serde_str_formats! {
+    UTF8: "a string";
+    ASCII: "an ASCII string";
+}
+
+macro_rules! serde_bytes_formats {
+    ($($fmt:ident: $expected:expr;)*) => {$(
+        impl<A> Serialize for Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: Serializer,
+            {
+                serializer.serialize_bytes(self.as_bytes())
+            }
+        }
+
+        impl<'de, A> Deserialize<'de> for Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: Deserializer<'de>,
+            {
+                deserializer.deserialize_bytes(BytesVisitor {
+                    expected: $expected,
+                    marker: PhantomData,
+                })
+            }
+        }
+    )*};
+}
+
+serde_bytes_formats! {
+    Bytes: "a byte string";
+    WTF8: "a WTF-8 byte string";
+    UTF16LE: "a UTF-16LE byte string";
+    UTF16BE: "a UTF-16BE byte string";
+    WTF16LE: "a WTF-16LE byte string";
+    WTF16BE: "a WTF-16BE byte string";
+    CESU8: "a CESU-8 byte string";
+    MUTF8: "a Modified UTF-8 byte string";
+}
+
+impl<A> Serialize for Tendril<fmt::Latin1, A>
+where
+    A: Atomicity,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let s: String = self.as_bytes().iter().map(|&b| b as char).collect();
+        serializer.serialize_str(&s)
+    }
+}
+
+/// Visits a string whose characters are all in Latin-1.
+struct Latin1Visitor<A>(PhantomData<A>);
+
+impl<'de, A> Visitor<'de> for Latin1Visitor<A>
+where
+    A: Atomicity,
+{
+    type Value = Tendril<fmt::Latin1, A>;
+
+    fn expecting(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        f.write_str("a string of Latin-1 characters")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Tendril<fmt::Latin1, A>, E>
+    where
+        E: de::Error,
+    {
+        let mut t = Tendril::<fmt::Latin1, A>::with_capacity(v.len() as u32);
+        for c in v.chars() {
+            t.try_push_char(c)
+                .map_err(|()| E::invalid_value(Unexpected::Char(c), &self))?;
+        }
+        Ok(t)
+    }
+}
+
+impl<'de, A> Deserialize<'de> for Tendril<fmt::Latin1, A>
+where
+    A: Atomicity,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        deserializer.deserialize_str(Latin1Visitor(PhantomData))
+    }
+}
+
+impl<F, V, A> Serialize for Tendril<fmt::Refined<F, V>, A>
+where
+    F: fmt::Format,
+    V: fmt::Validator,
+    A: Atomicity,
+    Tendril<F, A>: Serialize,
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        self.as_superset::<F>().serialize(serializer)
+    }
+}
+
+impl<'de, F, V, A> Deserialize<'de> for Tendril<fmt::Refined<F, V>, A>
+where
+    F: fmt::Format,
+    V: fmt::Validator,
+    A: Atomicity,
+    Tendril<F, A>: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Tendril::<F, A>::deserialize(deserializer)?
+            .try_reinterpret()
+            .map_err(|_| de::Error::custom("tendril: contents rejected by validator"))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use fmt;
+    use serde_test::{
+        assert_de_tokens, assert_de_tokens_error, assert_ser_tokens, assert_tokens, Token,
+    };
+    use tendril::{ByteTendril, SliceExt, StrTendril, Tendril};
+
+    #[test]
+    fn strings() {
+        let t = "a string for serde".to_tendril();
+        assert_tokens(&t, &[Token::Str("a string for serde")]);
+        assert_de_tokens(&t, &[Token::BorrowedStr("a string for serde")]);
+        assert_de_tokens(&t, &[Token::String("a string for serde")]);
+        assert_de_tokens(&t, &[Token::Bytes(b"a string for serde")]);
+        assert_de_tokens_error::<StrTendril>(
+            &[Token::Bytes(b"\xff")],
+            "invalid value: byte array, expected a string",
+        );
+
+        let a: Tendril<fmt::ASCII> = b"ascii".to_tendril().try_reinterpret().unwrap();
+        assert_ser_tokens(&a, &[Token::Str("ascii")]);
+        assert_de_tokens_error::<Tendril<fmt::ASCII>>(
+            &[Token::Str("caf\u{e9}")],
+            "invalid value: string \"caf\u{e9}\", expected an ASCII string",
+        );
+    }
+
+    #[test]
+    fn latin1() {
+        let t: Tendril<fmt::Latin1> = b"caf\xe9".to_tendril().try_reinterpret().unwrap();
+        assert_ser_tokens(&t, &[Token::Str("caf\u{e9}")]);
+        assert_de_tokens_error::<Tendril<fmt::Latin1>>(
+            &[Token::Str("\u{20ac}")],
+            "invalid value: character `\u{20ac}`, expected a string of Latin-1 characters",
+        );
+    }
+
+    #[test]
+    fn bytes() {
+        let t = b"\x00\xffbytes".to_tendril();
+        assert_tokens(&t, &[Token::Bytes(b"\x00\xffbytes")]);
+        assert_de_tokens(&t, &[Token::BorrowedBytes(b"\x00\xffbytes")]);
+        assert_de_tokens(&t, &[Token::ByteBuf(b"\x00\xffbytes")]);
+        assert_de_tokens(
+            &b"ab".to_tendril(),
+            &[
+                Token::Seq { len: Some(2) },
+                Token::U8(b'a'),
+                Token::U8(b'b'),
+                Token::SeqEnd,
+            ],
+        );
+        assert_de_tokens::<ByteTendril>(&ByteTendril::new(), &[Token::Bytes(b"")]);
+
+        assert_de_tokens_error::<Tendril<fmt::WTF8>>(
+            &[Token::Bytes(b"\xed\xa0\xbd\xed\xb2\xa9")],
+            "invalid value: byte array, expected a WTF-8 byte string",
+        );
+        assert_de_tokens_error::<Tendril<fmt::UTF16LE>>(
+            &[Token::Bytes(b"a")],
+            "invalid value: byte array, expected a UTF-16LE byte string",
+        );
+    }
+}
@@ -0,0 +1,362 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Binary snapshots of many tendrils, which store each shared buffer once.
+//!
+//! The encoding is a list of buffers followed by a list of views, each a
+//! (buffer id, offset, length) triple. All integers are little-endian
+//! `u32`s, and each buffer is prefixed by its length.
+
+use std::collections::HashMap;
+use std::{cmp, io, slice};
+
+use fmt;
+use tendril::{Atomicity, NonAtomic, ReadExt, Tendril};
+
+const MAGIC: &[u8; 8] = b"tendril1";
+
+/// The part of a buffer which is covered by some view.
+struct Span {
+    /// One of the views, which keeps the buffer alive.
+    view: usize,
+    start: u32,
+    end: u32,
+}
+
+/// Collects tendrils, and writes them with each shared buffer stored once.
+///
+/// Tendrils which are `subtendril`s of the same buffer are found using the
+/// buffer's address. Only the part of each buffer covered by some view is
+/// written.
+///
+/// The writer holds a reference to every tendril pushed. An unshared
+/// buffer becomes shared by this, as if by `clone`.
+pub struct SnapshotWriter<A = NonAtomic>
+where
+    A: Atomicity,
+{
+    spans: Vec<Span>,
+    ids: HashMap<usize, usize>,
+    views: Vec<(usize, Tendril<fmt::Bytes, A>)>,
+}
+
+impl<A> SnapshotWriter<A>
+where
+    A: Atomicity,
+{
+    /// Create an empty snapshot.
+    #[inline]
+    pub fn new() -> SnapshotWriter<A> {
+        SnapshotWriter {
+            spans: Vec::new(),
+            ids: HashMap::new(),
+            views: Vec::new(),
+        }
+    }
+
+    /// Add a tendril, and get the index at which `SnapshotReader::get`
+    /// will find it.
+    pub fn push<F>(&mut self, t: &Tendril<F, A>) -> usize
+    where
+        F: fmt::Format,
+    {
+        let t = t.as_bytes().clone();
+        let index = self.views.len();
+        let (start, end) = match t.shared_buffer() {
+            Some((_, offset)) => (offset, offset + t.len32()),
+            None => (0, t.len32()),
+        };
+
+        let id = match t.shared_buffer().and_then(|(addr, _)| self.ids.get(&addr)) {
+            Some(&id) => {
+                let span = &mut self.spans[id];
+                span.start = cmp::min(span.start, start);
+                span.end = cmp::max(span.end, end);
+                id
+            }
+            None => {
+                let id = self.spans.len();
+                if let Some((addr, _)) = t.shared_buffer() {
+                    self.ids.insert(addr, id);
+                }
+                self.spans.push(Span {
+                    view: index,
+                    start,
+                    end,
+                });
+                id
+            }
+        };
+        self.views.push((id, t));
+        index
+    }
+
+    /// Get the number of tendrils added.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Is the snapshot empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// Get the number of distinct buffers which will be written.
+    #[inline]
+    pub fn buffer_count(&self) -> usize {
+        self.spans.len()
+    }
+
+    /// Write the snapshot.
+    pub fn write_to<W>(&self, w: &mut W) -> io::Result<()>
+    where
+        W: io::Write,
+    {
+        w.write_all(MAGIC)?;
+        write_u32(w, self.spans.len() as u32)?;
+        for span in &self.spans {
+            write_u32(w, span.end - span.start)?;
+            w.write_all(self.span_bytes(span))?;
+        }
+        write_u32(w, self.views.len() as u32)?;
+        for &(id, ref t) in &self.views {
+            let offset = t.shared_buffer().map_or(0, |(_, o)| o);
+            write_u32(w, id as u32)?;
+            write_u32(w, offset - self.spans[id].start)?;
+            write_u32(w, t.len32())?;
+        }
+        Ok(())
+    }
+
+    fn span_bytes(&self, span: &Span) -> &[u8] {
+        let t = &self.views[span.view].1;
+        let offset = t.shared_buffer().map_or(0, |(_, o)| o);
+        // Every byte between views of a buffer was part of the tendril
+        // they were sliced from, so it's initialized. The buffer is kept
+        // alive by `t`.
+        unsafe {
+            let base = t.as_ptr().offset(-(offset as isize));
+            slice::from_raw_parts(
+                base.offset(span.start as isize),
+                (span.end - span.start) as usize,
+            )
+        }
+    }
+}
+
+impl<A> Default for SnapshotWriter<A>
+where
+    A: Atomicity,
+{
+    #[inline]
+    fn default() -> SnapshotWriter<A> {
+        SnapshotWriter::new()
+    }
+}
+
+/// The tendrils read back from a snapshot.
+///
+/// Views which were written from the same buffer share one buffer again.
+pub struct SnapshotReader<A = NonAtomic>
+where
+    A: Atomicity,
+{
+    buffer_count: usize,
+    views: Vec<Tendril<fmt::Bytes, A>>,
+}
+
+impl<A> SnapshotReader<A>
+where
+    A: Atomicity,
+{
+    /// Read a snapshot written by `SnapshotWriter::write_to`.
+    pub fn read_from<R>(r: &mut R) -> io::Result<SnapshotReader<A>>
+    where
+        R: io::Read,
+    {
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid("not a tendril snapshot"));
+        }
+
+        let buffer_count = read_u32(r)? as usize;
+        let mut buffers = Vec::with_capacity(cmp::min(buffer_count, 1024));
+        for _ in 0..buffer_count {
+            let len = read_u32(r)?;
+            // Don't trust the length for the allocation; let the read grow it.
+            let mut buf = Tendril::<fmt::Bytes, A>::with_capacity(cmp::min(len, 64 * 1024));
+            let mut data = io::Read::take(&mut *r, len as u64);
+            if data.read_to_tendril(&mut buf)? != len as usize {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            buffers.push(buf);
+        }
+
+        let view_count = read_u32(r)? as usize;
+        let mut views = Vec::with_capacity(cmp::min(view_count, 1024));
+        for _ in 0..view_count {
+            let id = read_u32(r)? as usize;
+            let offset = read_u32(r)?;
+            let len = read_u32(r)?;
+            let buf = buffers.get(id).ok_or_else(|| invalid("bad buffer id"))?;
+            let view = buf
+                .try_subtendril(offset, len)
+                .map_err(|_| invalid("view out of bounds"))?;
+            views.push(view);
+        }
+
+        Ok(SnapshotReader {
+            buffer_count,
+            views,
+        })
+    }
+
+    /// Get the number of tendrils in the snapshot.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.views.len()
+    }
+
+    /// Is the snapshot empty?
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.views.is_empty()
+    }
+
+    /// Get the number of distinct buffers in the snapshot.
+    #[inline]
+    pub fn buffer_count(&self) -> usize {
+        self.buffer_count
+    }
+
+    /// Get a tendril by the index returned from `SnapshotWriter::push`.
+    ///
+    /// Returns `None` if there's no such tendril, or if it isn't valid in
+    /// the format `F`.
+    #[inline]
+    pub fn get<F>(&self, index: usize) -> Option<Tendril<F, A>>
+    where
+        F: fmt::Format,
+    {
+        self.views.get(index)?.clone().try_reinterpret().ok()
+    }
+}
+
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn write_u32<W>(w: &mut W, n: u32) -> io::Result<()>
+where
+    W: io::Write,
+{
+    w.write_all(&[n as u8, (n >> 8) as u8, (n >> 16) as u8, (n >> 24) as u8])
+}
+
+fn read_u32<R>(r: &mut R) -> io::Result<u32>
+where
+    R: io::Read,
+{
+    let mut b = [0; 4];
+    r.read_exact(&mut b)?;
+    Ok(b[0] as u32 | (b[1] as u32) << 8 | (b[2] as u32) << 16 | (b[3] as u32) << 24)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{SnapshotReader, SnapshotWriter};
+    use fmt;
+    use std::io;
+    use tendril::{NonAtomic, SliceExt, StrTendril, Tendril};
+
+    #[test]
+    fn round_trip() {
+        let doc = "<p>Some text in a document</p><p>Another long paragraph</p>".to_tendril();
+        let other = "a separate buffer, also quite long".to_tendril();
+        let a = doc.subtendril(3, 23);
+        let b = doc.subtendril(33, 22);
+        let c = doc.subtendril(8, 4);
+
+        let mut writer = SnapshotWriter::new();
+        assert_eq!(0, writer.push(&a));
+        assert_eq!(1, writer.push(&other));
+        assert_eq!(2, writer.push(&b));
+        assert_eq!(3, writer.push(&c));
+        assert_eq!(4, writer.push(&"inline".to_tendril()));
+        // `c` and the last one are inline, so they have no buffer to share.
+        assert_eq!(4, writer.buffer_count());
+
+        let mut out = vec![];
+        writer.write_to(&mut out).unwrap();
+        // Only the part of `doc` between `a` and `b` is written.
+        assert_eq!(1, out.windows(3).filter(|w| w == b"<p>").count());
+
+        let reader = SnapshotReader::read_from(&mut &*out).unwrap();
+        assert_eq!(5, reader.len());
+        assert_eq!(4, reader.buffer_count());
+        let a2: StrTendril = reader.get(0).unwrap();
+        let b2: StrTendril = reader.get(2).unwrap();
+        assert_eq!(a, a2);
+        assert_eq!(other, reader.get(1).unwrap());
+        assert_eq!(b, b2);
+        assert_eq!(c, reader.get(3).unwrap());
+        assert_eq!("inline", &*reader.get::<fmt::UTF8>(4).unwrap());
+        assert!(reader.get::<fmt::UTF8>(5).is_none());
+
+        // The views share one buffer again, laid out as before.
+        assert!(a2.is_shared_with(&b2));
+        assert_eq!(30, b2.as_ptr() as usize - a2.as_ptr() as usize);
+    }
+
+    #[test]
+    fn format_check() {
+        let bytes = b"\xff is not UTF-8, but is fine as bytes".to_tendril();
+        let mut writer = SnapshotWriter::new();
+        writer.push(&bytes);
+        let mut out = vec![];
+        writer.write_to(&mut out).unwrap();
+
+        let reader = SnapshotReader::read_from(&mut &*out).unwrap();
+        assert!(reader.get::<fmt::UTF8>(0).is_none());
+        assert_eq!(bytes, reader.get::<fmt::Bytes>(0).unwrap());
+    }
+
+    #[test]
+    fn corrupt() {
+        let mut writer = SnapshotWriter::<NonAtomic>::new();
+        writer.push(&Tendril::<fmt::UTF8>::from_slice("some text in a buffer"));
+        let mut out = vec![];
+        writer.write_to(&mut out).unwrap();
+
+        let err = |data: &[u8]| {
+            SnapshotReader::<NonAtomic>::read_from(&mut &*data)
+                .err()
+                .unwrap()
+                .kind()
+        };
+        assert_eq!(io::ErrorKind::InvalidData, err(b"not a snapshot"));
+        assert_eq!(io::ErrorKind::UnexpectedEof, err(&out[..20]));
+        let mut bad = out.clone();
+        let n = bad.len();
+        bad[n - 4] = 100;
+        assert_eq!(io::ErrorKind::InvalidData, err(&bad));
+        bad[n - 12] = 1;
+        assert_eq!(io::ErrorKind::InvalidData, err(&bad));
+    }
+
+    #[test]
+    fn huge_length() {
+        let mut data = b"tendril1".to_vec();
+        data.extend_from_slice(&[1, 0, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        let err = SnapshotReader::<NonAtomic>::read_from(&mut &*data)
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::UnexpectedEof, err.kind());
+    }
+}
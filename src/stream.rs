@@ -7,18 +7,24 @@
 //! Streams of tendrils.
 
 use fmt;
+use pool::TendrilPool;
 use tendril::{Atomicity, NonAtomic, Tendril};
 
 use std::borrow::Cow;
+use std::convert::Infallible;
 use std::fs::File;
 use std::io;
 use std::marker::PhantomData;
+#[cfg(feature = "unicode-normalization")]
+use std::mem;
 use std::path::Path;
 
 #[cfg(feature = "encoding")]
 use encoding;
 #[cfg(feature = "encoding_rs")]
 use encoding_rs::{self, DecoderResult};
+#[cfg(feature = "unicode-normalization")]
+use normalize::NormalizationForm;
 use utf8;
 
 /// Trait for types that can process a tendril.
@@ -112,6 +118,316 @@ where
     {
         self.read_from(&mut File::open(path)?)
     }
+
+    /// Like `read_from`, but read into buffers taken from `pool`.
+    ///
+    /// Buffers go back to the pool once the sink has dropped every tendril
+    /// it was given.
+    fn read_from_pooled<R>(mut self, r: &mut R, pool: &TendrilPool) -> io::Result<Self::Output>
+    where
+        Self: Sized,
+        R: io::Read,
+        F: fmt::SliceFormat<Slice = [u8]>,
+    {
+        let buffer_size = pool.buffer_size();
+        loop {
+            let mut tendril = pool.get::<F, A>();
+            // FIXME: same as in `read_from`.
+            unsafe {
+                tendril.push_uninitialized(buffer_size);
+            }
+            loop {
+                match r.read(&mut tendril) {
+                    Ok(0) => return Ok(self.finish()),
+                    Ok(n) => {
+                        tendril.pop_back(buffer_size - n as u32);
+                        self.process(tendril);
+                        break;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Like `from_file`, but read into buffers taken from `pool`.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_file_pooled<P>(self, path: P, pool: &TendrilPool) -> io::Result<Self::Output>
+    where
+        Self: Sized,
+        P: AsRef<Path>,
+        F: fmt::SliceFormat<Slice = [u8]>,
+    {
+        self.read_from_pooled(&mut File::open(path)?, pool)
+    }
+}
+
+/// Trait for types that can process a tendril, but may fail.
+///
+/// This is `TendrilSink` with `process` and `finish` returning a `Result`.
+/// The provided methods, and the decoders in this module, stop at the
+/// first error. Use `TryAdapter` and `FirstError` to convert between the
+/// two traits.
+pub trait TryTendrilSink<F, A = NonAtomic>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// The type of errors from processing.
+    type Error;
+
+    /// Process this tendril.
+    fn process(&mut self, t: Tendril<F, A>) -> Result<(), Self::Error>;
+
+    /// Indicates that an error has occurred upstream.
+    fn error(&mut self, desc: Cow<'static, str>);
+
+    /// What the overall result of processing is.
+    type Output;
+
+    /// Indicates the end of the stream.
+    fn finish(self) -> Result<Self::Output, Self::Error>;
+
+    /// Process one tendril and finish.
+    fn one<T>(mut self, t: T) -> Result<Self::Output, Self::Error>
+    where
+        Self: Sized,
+        T: Into<Tendril<F, A>>,
+    {
+        self.process(t.into())?;
+        self.finish()
+    }
+
+    /// Consume an iterator of tendrils, processing each item, then finish.
+    /// Return `Err` at the first error, without consuming the rest.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_iter<I>(mut self, i: I) -> Result<Self::Output, Self::Error>
+    where
+        Self: Sized,
+        I: IntoIterator,
+        I::Item: Into<Tendril<F, A>>,
+    {
+        for t in i {
+            self.process(t.into())?;
+        }
+        self.finish()
+    }
+
+    /// Read from the given stream of bytes until exhaustion and process incrementally,
+    /// then finish. Return `Err` at the first I/O or processing error.
+    fn read_from<R>(mut self, r: &mut R) -> Result<Self::Output, Self::Error>
+    where
+        Self: Sized,
+        Self::Error: From<io::Error>,
+        R: io::Read,
+        F: fmt::SliceFormat<Slice = [u8]>,
+    {
+        const BUFFER_SIZE: u32 = 4 * 1024;
+        loop {
+            let mut tendril = Tendril::<F, A>::new();
+            // FIXME: same as in `TendrilSink::read_from`.
+            unsafe {
+                tendril.push_uninitialized(BUFFER_SIZE);
+            }
+            loop {
+                match r.read(&mut tendril) {
+                    Ok(0) => return self.finish(),
+                    Ok(n) => {
+                        tendril.pop_back(BUFFER_SIZE - n as u32);
+                        self.process(tendril)?;
+                        break;
+                    }
+                    Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+        }
+    }
+
+    /// Read from the file at the given path and process incrementally,
+    /// then finish. Return `Err` at the first I/O or processing error.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_file<P>(self, path: P) -> Result<Self::Output, Self::Error>
+    where
+        Self: Sized,
+        Self::Error: From<io::Error>,
+        P: AsRef<Path>,
+        F: fmt::SliceFormat<Slice = [u8]>,
+    {
+        self.read_from(&mut File::open(path)?)
+    }
+}
+
+/// Use a `TendrilSink` where a `TryTendrilSink` is expected. It never
+/// fails.
+pub struct TryAdapter<Sink> {
+    pub inner_sink: Sink,
+}
+
+impl<Sink> TryAdapter<Sink> {
+    /// Wrap a `TendrilSink`.
+    #[inline]
+    pub fn new(inner_sink: Sink) -> Self {
+        TryAdapter { inner_sink }
+    }
+}
+
+impl<Sink, F, A> TryTendrilSink<F, A> for TryAdapter<Sink>
+where
+    Sink: TendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Error = Infallible;
+
+    #[inline]
+    fn process(&mut self, t: Tendril<F, A>) -> Result<(), Infallible> {
+        self.inner_sink.process(t);
+        Ok(())
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.inner_sink.error(desc);
+    }
+
+    type Output = Sink::Output;
+
+    #[inline]
+    fn finish(self) -> Result<Sink::Output, Infallible> {
+        Ok(self.inner_sink.finish())
+    }
+}
+
+/// Use a `TryTendrilSink` where a `TendrilSink` is expected.
+///
+/// The first error is remembered, later tendrils are dropped, and the
+/// error is returned by `finish`.
+pub struct FirstError<Sink, F, A = NonAtomic>
+where
+    Sink: TryTendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    pub inner_sink: Sink,
+    error: Option<Sink::Error>,
+    marker: PhantomData<(F, A)>,
+}
+
+impl<Sink, F, A> FirstError<Sink, F, A>
+where
+    Sink: TryTendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Wrap a `TryTendrilSink`.
+    #[inline]
+    pub fn new(inner_sink: Sink) -> Self {
+        FirstError {
+            inner_sink,
+            error: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Has the inner sink failed?
+    #[inline]
+    pub fn has_failed(&self) -> bool {
+        self.error.is_some()
+    }
+}
+
+impl<Sink, F, A> TendrilSink<F, A> for FirstError<Sink, F, A>
+where
+    Sink: TryTendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn process(&mut self, t: Tendril<F, A>) {
+        if self.error.is_none() {
+            if let Err(e) = self.inner_sink.process(t) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        if self.error.is_none() {
+            self.inner_sink.error(desc);
+        }
+    }
+
+    type Output = Result<Sink::Output, Sink::Error>;
+
+    #[inline]
+    fn finish(self) -> Result<Sink::Output, Sink::Error> {
+        match self.error {
+            Some(e) => Err(e),
+            None => self.inner_sink.finish(),
+        }
+    }
+}
+
+/// Where the decoders send their output, so that each is written once for
+/// both `TendrilSink` and `TryTendrilSink`.
+trait Emit<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Error;
+
+    fn emit(&mut self, t: Tendril<F, A>) -> Result<(), Self::Error>;
+
+    fn emit_error(&mut self, desc: Cow<'static, str>);
+}
+
+/// Emits to a `TendrilSink`.
+struct Infallibly<'a, Sink: 'a>(&'a mut Sink);
+
+impl<'a, Sink, F, A> Emit<F, A> for Infallibly<'a, Sink>
+where
+    Sink: TendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Error = Infallible;
+
+    #[inline]
+    fn emit(&mut self, t: Tendril<F, A>) -> Result<(), Infallible> {
+        self.0.process(t);
+        Ok(())
+    }
+
+    #[inline]
+    fn emit_error(&mut self, desc: Cow<'static, str>) {
+        self.0.error(desc);
+    }
+}
+
+/// Emits to a `TryTendrilSink`.
+struct Fallibly<'a, Sink: 'a>(&'a mut Sink);
+
+impl<'a, Sink, F, A> Emit<F, A> for Fallibly<'a, Sink>
+where
+    Sink: TryTendrilSink<F, A>,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    type Error = Sink::Error;
+
+    #[inline]
+    fn emit(&mut self, t: Tendril<F, A>) -> Result<(), Sink::Error> {
+        self.0.process(t)
+    }
+
+    #[inline]
+    fn emit_error(&mut self, desc: Cow<'static, str>) {
+        self.0.error(desc);
+    }
 }
 
 /// A `TendrilSink` adaptor that takes bytes, decodes them as UTF-8,
@@ -120,9 +436,10 @@ where
 ///
 /// This does not allocate memory: the output is either subtendrils on the input,
 /// on inline tendrils for a single code point.
+///
+/// It is also a `TryTendrilSink` when `Sink` is one.
 pub struct Utf8LossyDecoder<Sink, A = NonAtomic>
 where
-    Sink: TendrilSink<fmt::UTF8, A>,
     A: Atomicity,
 {
     pub inner_sink: Sink,
@@ -132,7 +449,6 @@ where
 
 impl<Sink, A> Utf8LossyDecoder<Sink, A>
 where
-    Sink: TendrilSink<fmt::UTF8, A>,
     A: Atomicity,
 {
     /// Create a new incremental UTF-8 decoder.
@@ -144,35 +460,35 @@ where
             marker: PhantomData,
         }
     }
-}
 
-impl<Sink, A> TendrilSink<fmt::Bytes, A> for Utf8LossyDecoder<Sink, A>
-where
-    Sink: TendrilSink<fmt::UTF8, A>,
-    A: Atomicity,
-{
-    #[inline]
-    fn process(&mut self, mut t: Tendril<fmt::Bytes, A>) {
-        // FIXME: remove take() and map() when non-lexical borrows are stable.
+    fn decode<'s, E, W>(
+        &'s mut self,
+        mut t: Tendril<fmt::Bytes, A>,
+        wrap: W,
+    ) -> Result<(), E::Error>
+    where
+        E: Emit<fmt::UTF8, A>,
+        W: FnOnce(&'s mut Sink) -> E,
+    {
+        let sink = &mut wrap(&mut self.inner_sink);
         if let Some(mut incomplete) = self.incomplete.take() {
-            let resume_at = incomplete.try_complete(&t).map(|(result, rest)| {
-                match result {
-                    Ok(s) => self.inner_sink.process(Tendril::from_slice(s)),
-                    Err(_) => {
-                        self.inner_sink.error("invalid byte sequence".into());
-                        self.inner_sink
-                            .process(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER));
-                    }
-                }
-                t.len() - rest.len()
-            });
-            match resume_at {
+            let resume_at = match incomplete.try_complete(&t) {
                 None => {
                     self.incomplete = Some(incomplete);
-                    return;
+                    return Ok(());
                 }
-                Some(resume_at) => t.pop_front(resume_at as u32),
-            }
+                Some((result, rest)) => {
+                    match result {
+                        Ok(s) => sink.emit(Tendril::from_slice(s))?,
+                        Err(_) => {
+                            sink.emit_error("invalid byte sequence".into());
+                            sink.emit(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER))?;
+                        }
+                    }
+                    t.len() - rest.len()
+                }
+            };
+            t.pop_front(resume_at as u32);
         }
         while !t.is_empty() {
             let unborrowed_result = match utf8::decode(&t) {
@@ -204,32 +520,52 @@ where
             };
             match unborrowed_result {
                 Ok(()) => {
-                    unsafe { self.inner_sink.process(t.reinterpret_without_validating()) }
-                    return;
+                    return sink.emit(unsafe { t.reinterpret_without_validating() });
                 }
                 Err((valid_len, and_then)) => {
                     if valid_len > 0 {
                         let subtendril = t.subtendril(0, valid_len as u32);
-                        unsafe {
-                            self.inner_sink
-                                .process(subtendril.reinterpret_without_validating())
-                        }
+                        sink.emit(unsafe { subtendril.reinterpret_without_validating() })?;
                     }
                     match and_then {
                         Ok(incomplete) => {
                             self.incomplete = Some(incomplete);
-                            return;
+                            return Ok(());
                         }
                         Err(offset) => {
-                            self.inner_sink.error("invalid byte sequence".into());
-                            self.inner_sink
-                                .process(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER));
+                            sink.emit_error("invalid byte sequence".into());
+                            sink.emit(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER))?;
                             t.pop_front(offset as u32);
                         }
                     }
                 }
             }
         }
+        Ok(())
+    }
+
+    fn finish_decoding<'s, E, W>(&'s mut self, wrap: W) -> Result<(), E::Error>
+    where
+        E: Emit<fmt::UTF8, A>,
+        W: FnOnce(&'s mut Sink) -> E,
+    {
+        if self.incomplete.is_some() {
+            let mut sink = wrap(&mut self.inner_sink);
+            sink.emit_error("incomplete byte sequence at end of stream".into());
+            sink.emit(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER))?;
+        }
+        Ok(())
+    }
+}
+
+impl<Sink, A> TendrilSink<fmt::Bytes, A> for Utf8LossyDecoder<Sink, A>
+where
+    Sink: TendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    #[inline]
+    fn process(&mut self, t: Tendril<fmt::Bytes, A>) {
+        let _ = self.decode(t, Infallibly);
     }
 
     #[inline]
@@ -241,12 +577,33 @@ where
 
     #[inline]
     fn finish(mut self) -> Sink::Output {
-        if self.incomplete.is_some() {
-            self.inner_sink
-                .error("incomplete byte sequence at end of stream".into());
-            self.inner_sink
-                .process(Tendril::from_slice(utf8::REPLACEMENT_CHARACTER));
-        }
+        let _ = self.finish_decoding(Infallibly);
+        self.inner_sink.finish()
+    }
+}
+
+impl<Sink, A> TryTendrilSink<fmt::Bytes, A> for Utf8LossyDecoder<Sink, A>
+where
+    Sink: TryTendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    type Error = Sink::Error;
+
+    #[inline]
+    fn process(&mut self, t: Tendril<fmt::Bytes, A>) -> Result<(), Sink::Error> {
+        self.decode(t, Fallibly)
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.inner_sink.error(desc);
+    }
+
+    type Output = Sink::Output;
+
+    #[inline]
+    fn finish(mut self) -> Result<Sink::Output, Sink::Error> {
+        self.finish_decoding(Fallibly)?;
         self.inner_sink.finish()
     }
 }
@@ -256,10 +613,11 @@ where
 /// and emits Unicode (`StrTendril`).
 ///
 /// This allocates new tendrils for encodings other than UTF-8.
+///
+/// It is also a `TryTendrilSink` when `Sink` is one.
 #[cfg(any(feature = "encoding", feature = "encoding_rs"))]
 pub struct LossyDecoder<Sink, A = NonAtomic>
 where
-    Sink: TendrilSink<fmt::UTF8, A>,
     A: Atomicity,
 {
     inner: LossyDecoderInner<Sink, A>,
@@ -268,7 +626,6 @@ where
 #[cfg(any(feature = "encoding", feature = "encoding_rs"))]
 enum LossyDecoderInner<Sink, A>
 where
-    Sink: TendrilSink<fmt::UTF8, A>,
     A: Atomicity,
 {
     Utf8(Utf8LossyDecoder<Sink, A>),
@@ -281,7 +638,6 @@ where
 #[cfg(any(feature = "encoding", feature = "encoding_rs"))]
 impl<Sink, A> LossyDecoder<Sink, A>
 where
-    Sink: TendrilSink<fmt::UTF8, A>,
     A: Atomicity,
 {
     /// Create a new incremental decoder using the encoding crate.
@@ -341,27 +697,34 @@ where
             LossyDecoderInner::EncodingRs(_, ref mut inner_sink) => inner_sink,
         }
     }
-}
 
-#[cfg(any(feature = "encoding", feature = "encoding_rs"))]
-impl<Sink, A> TendrilSink<fmt::Bytes, A> for LossyDecoder<Sink, A>
-where
-    Sink: TendrilSink<fmt::UTF8, A>,
-    A: Atomicity,
-{
-    #[inline]
-    fn process(&mut self, t: Tendril<fmt::Bytes, A>) {
+    fn into_inner_sink(self) -> Sink {
+        match self.inner {
+            LossyDecoderInner::Utf8(utf8) => utf8.inner_sink,
+            #[cfg(feature = "encoding")]
+            LossyDecoderInner::Encoding(_, inner_sink) => inner_sink,
+            #[cfg(feature = "encoding_rs")]
+            LossyDecoderInner::EncodingRs(_, inner_sink) => inner_sink,
+        }
+    }
+
+    fn decode<'s, E, W>(&'s mut self, t: Tendril<fmt::Bytes, A>, wrap: W) -> Result<(), E::Error>
+    where
+        E: Emit<fmt::UTF8, A>,
+        W: FnOnce(&'s mut Sink) -> E,
+    {
         match self.inner {
-            LossyDecoderInner::Utf8(ref mut utf8) => return utf8.process(t),
+            LossyDecoderInner::Utf8(ref mut utf8) => utf8.decode(t, wrap),
             #[cfg(feature = "encoding")]
             LossyDecoderInner::Encoding(ref mut decoder, ref mut sink) => {
+                let sink = &mut wrap(sink);
                 let mut out = Tendril::new();
                 let mut t = t;
                 loop {
                     match decoder.raw_feed(&*t, &mut out) {
                         (_, Some(err)) => {
                             out.push_char('\u{fffd}');
-                            sink.error(err.cause);
+                            sink.emit_error(err.cause);
                             debug_assert!(err.upto >= 0);
                             t.pop_front(err.upto as u32);
                             // continue loop and process remainder of t
@@ -370,65 +733,109 @@ where
                     }
                 }
                 if out.len() > 0 {
-                    sink.process(out);
+                    sink.emit(out)?;
                 }
+                Ok(())
             }
             #[cfg(feature = "encoding_rs")]
             LossyDecoderInner::EncodingRs(ref mut decoder, ref mut sink) => {
                 if t.is_empty() {
-                    return;
+                    return Ok(());
                 }
-                decode_to_sink(t, decoder, sink, false);
+                decode_to_sink(t, decoder, &mut wrap(sink), false)
             }
         }
     }
 
-    #[inline]
-    fn error(&mut self, desc: Cow<'static, str>) {
-        match self.inner {
-            LossyDecoderInner::Utf8(ref mut utf8) => utf8.error(desc),
-            #[cfg(feature = "encoding")]
-            LossyDecoderInner::Encoding(_, ref mut sink) => sink.error(desc),
-            #[cfg(feature = "encoding_rs")]
-            LossyDecoderInner::EncodingRs(_, ref mut sink) => sink.error(desc),
-        }
-    }
-
-    type Output = Sink::Output;
-
-    #[inline]
-    fn finish(self) -> Sink::Output {
+    fn finish_decoding<'s, E, W>(&'s mut self, wrap: W) -> Result<(), E::Error>
+    where
+        E: Emit<fmt::UTF8, A>,
+        W: FnOnce(&'s mut Sink) -> E,
+    {
         match self.inner {
-            LossyDecoderInner::Utf8(utf8) => return utf8.finish(),
+            LossyDecoderInner::Utf8(ref mut utf8) => utf8.finish_decoding(wrap),
             #[cfg(feature = "encoding")]
-            LossyDecoderInner::Encoding(mut decoder, mut sink) => {
+            LossyDecoderInner::Encoding(ref mut decoder, ref mut sink) => {
+                let sink = &mut wrap(sink);
                 let mut out = Tendril::new();
                 if let Some(err) = decoder.raw_finish(&mut out) {
                     out.push_char('\u{fffd}');
-                    sink.error(err.cause);
+                    sink.emit_error(err.cause);
                 }
                 if out.len() > 0 {
-                    sink.process(out);
+                    sink.emit(out)?;
                 }
-                sink.finish()
+                Ok(())
             }
             #[cfg(feature = "encoding_rs")]
-            LossyDecoderInner::EncodingRs(mut decoder, mut sink) => {
-                decode_to_sink(Tendril::new(), &mut decoder, &mut sink, true);
-                sink.finish()
+            LossyDecoderInner::EncodingRs(ref mut decoder, ref mut sink) => {
+                decode_to_sink(Tendril::new(), decoder, &mut wrap(sink), true)
             }
         }
     }
 }
 
+#[cfg(any(feature = "encoding", feature = "encoding_rs"))]
+impl<Sink, A> TendrilSink<fmt::Bytes, A> for LossyDecoder<Sink, A>
+where
+    Sink: TendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    #[inline]
+    fn process(&mut self, t: Tendril<fmt::Bytes, A>) {
+        let _ = self.decode(t, Infallibly);
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.inner_sink_mut().error(desc);
+    }
+
+    type Output = Sink::Output;
+
+    #[inline]
+    fn finish(mut self) -> Sink::Output {
+        let _ = self.finish_decoding(Infallibly);
+        self.into_inner_sink().finish()
+    }
+}
+
+#[cfg(any(feature = "encoding", feature = "encoding_rs"))]
+impl<Sink, A> TryTendrilSink<fmt::Bytes, A> for LossyDecoder<Sink, A>
+where
+    Sink: TryTendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    type Error = Sink::Error;
+
+    #[inline]
+    fn process(&mut self, t: Tendril<fmt::Bytes, A>) -> Result<(), Sink::Error> {
+        self.decode(t, Fallibly)
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.inner_sink_mut().error(desc);
+    }
+
+    type Output = Sink::Output;
+
+    #[inline]
+    fn finish(mut self) -> Result<Sink::Output, Sink::Error> {
+        self.finish_decoding(Fallibly)?;
+        self.into_inner_sink().finish()
+    }
+}
+
 #[cfg(feature = "encoding_rs")]
-fn decode_to_sink<Sink, A>(
+fn decode_to_sink<E, A>(
     mut t: Tendril<fmt::Bytes, A>,
     decoder: &mut encoding_rs::Decoder,
-    sink: &mut Sink,
+    sink: &mut E,
     last: bool,
-) where
-    Sink: TendrilSink<fmt::UTF8, A>,
+) -> Result<(), E::Error>
+where
+    E: Emit<fmt::UTF8, A>,
     A: Atomicity,
 {
     loop {
@@ -442,37 +849,243 @@ fn decode_to_sink<Sink, A>(
         let (result, bytes_read, bytes_written) =
             decoder.decode_to_utf8_without_replacement(&t, &mut out, last);
         if bytes_written > 0 {
-            sink.process(unsafe {
+            sink.emit(unsafe {
                 out.subtendril(0, bytes_written as u32)
                     .reinterpret_without_validating()
-            });
+            })?;
         }
         match result {
-            DecoderResult::InputEmpty => return,
+            DecoderResult::InputEmpty => return Ok(()),
             DecoderResult::OutputFull => {}
             DecoderResult::Malformed(_, _) => {
-                sink.error(Cow::Borrowed("invalid sequence"));
-                sink.process("\u{FFFD}".into());
+                sink.emit_error(Cow::Borrowed("invalid sequence"));
+                sink.emit("\u{FFFD}".into())?;
             }
         }
         t.pop_front(bytes_read as u32);
         if t.is_empty() {
-            return;
+            return Ok(());
         }
     }
 }
 
+/// A `TendrilSink` adaptor that converts Unicode text to one of the
+/// normalization forms, with the `unicode-normalization` feature.
+///
+/// Text after the last safe split point of each tendril is held back until
+/// the next one arrives, so combining sequences split across tendrils are
+/// normalized as a whole. Tendrils which are already normalized are passed
+/// on without copying.
+#[cfg(feature = "unicode-normalization")]
+pub struct Normalizer<Sink, A = NonAtomic>
+where
+    Sink: TendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    pub inner_sink: Sink,
+    form: NormalizationForm,
+    pending: Tendril<fmt::UTF8, A>,
+}
+
+#[cfg(feature = "unicode-normalization")]
+impl<Sink, A> Normalizer<Sink, A>
+where
+    Sink: TendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    /// Create a new incremental normalizer.
+    #[inline]
+    pub fn new(form: NormalizationForm, inner_sink: Sink) -> Self {
+        Normalizer {
+            inner_sink,
+            form,
+            pending: Tendril::new(),
+        }
+    }
+
+    fn emit(&mut self, t: &Tendril<fmt::UTF8, A>) {
+        if !t.is_empty() {
+            self.inner_sink.process(self.form.normalize(t));
+        }
+    }
+}
+
+#[cfg(feature = "unicode-normalization")]
+impl<Sink, A> TendrilSink<fmt::UTF8, A> for Normalizer<Sink, A>
+where
+    Sink: TendrilSink<fmt::UTF8, A>,
+    A: Atomicity,
+{
+    fn process(&mut self, t: Tendril<fmt::UTF8, A>) {
+        let form = self.form;
+        let split = t
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| form.is_boundary(c))
+            .map(|(i, _)| i as u32);
+        let split = match split {
+            Some(i) => i,
+            None if self.pending.is_empty() => {
+                self.pending = t;
+                return;
+            }
+            None => {
+                self.pending.push_tendril(&t);
+                return;
+            }
+        };
+
+        let head = t.subtendril(0, split);
+        let tail = t.subtendril(split, t.len32() - split);
+        if self.pending.is_empty() {
+            self.emit(&head);
+            self.pending = tail;
+        } else {
+            self.pending.push_tendril(&head);
+            let pending = mem::replace(&mut self.pending, tail);
+            self.emit(&pending);
+        }
+    }
+
+    #[inline]
+    fn error(&mut self, desc: Cow<'static, str>) {
+        self.inner_sink.error(desc);
+    }
+
+    type Output = Sink::Output;
+
+    #[inline]
+    fn finish(mut self) -> Sink::Output {
+        let pending = self.pending.clone();
+        self.emit(&pending);
+        self.inner_sink.finish()
+    }
+}
+
+/// A `TendrilSink` that writes everything it is given to an `io::Write`.
+///
+/// `process` can't return an error, so the first I/O error is remembered,
+/// later tendrils are dropped, and the error is returned by `finish`.
+/// Errors reported by the sender are ignored.
+///
+/// In line-buffered mode, output is held back until a newline arrives,
+/// and the writer is flushed after each batch of complete lines.
+pub struct WriteSink<W, F, A = NonAtomic>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    inner: W,
+    line_buffer: Option<Vec<u8>>,
+    error: Option<io::Error>,
+    marker: PhantomData<(F, A)>,
+}
+
+impl<W, F, A> WriteSink<W, F, A>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Create a sink which writes each tendril as it arrives.
+    #[inline]
+    pub fn new(inner: W) -> Self {
+        WriteSink {
+            inner,
+            line_buffer: None,
+            error: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Create a sink which writes whole lines at a time.
+    #[inline]
+    pub fn line_buffered(inner: W) -> Self {
+        WriteSink {
+            inner,
+            line_buffer: Some(Vec::new()),
+            error: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> io::Result<()> {
+        let buf = match self.line_buffer {
+            None => return self.inner.write_all(bytes),
+            Some(ref mut buf) => buf,
+        };
+        match bytes.iter().rposition(|&b| b == b'\n') {
+            None => buf.extend_from_slice(bytes),
+            Some(i) => {
+                let (lines, rest) = bytes.split_at(i + 1);
+                if !buf.is_empty() {
+                    self.inner.write_all(buf)?;
+                    buf.clear();
+                }
+                self.inner.write_all(lines)?;
+                self.inner.flush()?;
+                buf.extend_from_slice(rest);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<W, F, A> TendrilSink<F, A> for WriteSink<W, F, A>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn process(&mut self, t: Tendril<F, A>) {
+        if self.error.is_none() {
+            if let Err(e) = self.write(t.as_bytes()) {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    #[inline]
+    fn error(&mut self, _desc: Cow<'static, str>) {}
+
+    type Output = io::Result<W>;
+
+    #[inline]
+    fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        if let Some(ref buf) = self.line_buffer {
+            self.inner.write_all(buf)?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{TendrilSink, Utf8LossyDecoder};
+    use super::{FirstError, TendrilSink, TryAdapter, TryTendrilSink, Utf8LossyDecoder, WriteSink};
     use fmt;
+    use pool::TendrilPool;
     use std::borrow::Cow;
-    use tendril::{Atomicity, NonAtomic, Tendril};
+    use std::io;
+    use tendril::{Atomicity, NonAtomic, SliceExt, Tendril};
 
     #[cfg(any(feature = "encoding", feature = "encoding_rs"))]
     use super::LossyDecoder;
-    #[cfg(any(feature = "encoding", feature = "encoding_rs"))]
-    use tendril::SliceExt;
+    #[cfg(feature = "unicode-normalization")]
+    use super::Normalizer;
+    #[cfg(feature = "unicode-normalization")]
+    use normalize::NormalizationForm;
 
     #[cfg(feature = "encoding")]
     use encoding::all as enc;
@@ -749,4 +1362,227 @@ mod test {
         );
         assert_eq!(errors, &["invalid byte sequence"]);
     }
+
+    #[test]
+    fn read_from_pooled() {
+        let pool = TendrilPool::new(16);
+        let decoder = Utf8LossyDecoder::new(Accumulate::<NonAtomic>::new());
+        let mut bytes: &[u8] = b"foo\xffbar and some more text to span buffers";
+        let (tendrils, errors) = decoder.read_from_pooled(&mut bytes, &pool).unwrap();
+        assert_eq!(
+            "foo\u{FFFD}bar and some more text to span buffers",
+            tendrils.iter().map(|t| &**t).collect::<String>()
+        );
+        assert_eq!(errors, &["invalid byte sequence"]);
+        drop(tendrils);
+        assert!(pool.idle() > 0);
+    }
+
+    #[cfg(feature = "unicode-normalization")]
+    fn check_normalize(form: NormalizationForm, input: &[&str], expected: &[&str]) {
+        let normalizer = Normalizer::new(form, Accumulate::<NonAtomic>::new());
+        let (tendrils, errors) = normalizer.from_iter(input.iter().cloned());
+        assert_eq!(
+            expected,
+            &*tendrils.iter().map(|t| &**t).collect::<Vec<_>>()
+        );
+        assert!(errors.is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "unicode-normalization")]
+    fn normalize() {
+        use self::NormalizationForm::{NFC, NFD};
+
+        check_normalize(NFC, &[], &[]);
+        check_normalize(NFC, &["", "abc", "def"], &["ab", "cde", "f"]);
+        check_normalize(
+            NFC,
+            &["cafe", "\u{301} au lait"],
+            &["caf", "\u{e9} au lai", "t"],
+        );
+        check_normalize(NFC, &["e", "\u{301}", "\u{327}"], &["\u{229}\u{301}"]);
+        check_normalize(NFC, &["\u{1100}", "\u{1161}\u{11a8}x"], &["\u{ac01}", "x"]);
+        check_normalize(NFD, &["\u{e9}", "\u{327}!"], &["e\u{327}\u{301}", "!"]);
+
+        let long = "already normalized, and long enough to share";
+        let input = long.to_tendril();
+        let normalizer = Normalizer::new(NFC, Accumulate::<NonAtomic>::new());
+        let (tendrils, _) = normalizer.one(input.clone());
+        assert_eq!(input.as_ptr(), tendrils[0].as_ptr());
+    }
+
+    /// Records writes and flushes, and fails once `budget` bytes are used.
+    struct Recorder {
+        writes: Vec<Vec<u8>>,
+        flushes: usize,
+        budget: usize,
+    }
+
+    impl io::Write for Recorder {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            if buf.len() > self.budget {
+                return Err(io::Error::new(io::ErrorKind::Other, "out of space"));
+            }
+            self.budget -= buf.len();
+            self.writes.push(buf.to_vec());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            self.flushes += 1;
+            Ok(())
+        }
+    }
+
+    fn recorder(budget: usize) -> Recorder {
+        Recorder {
+            writes: vec![],
+            flushes: 0,
+            budget,
+        }
+    }
+
+    #[test]
+    fn write_sink() {
+        let sink = WriteSink::<_, fmt::UTF8>::new(recorder(100));
+        let w = sink.from_iter(vec!["one", "two", "three"]).unwrap();
+        assert_eq!(
+            vec![b"one".to_vec(), b"two".to_vec(), b"three".to_vec()],
+            w.writes
+        );
+        assert_eq!(1, w.flushes);
+
+        let mut bytes: &[u8] = b"read and written";
+        let sink = WriteSink::<_, fmt::Bytes>::new(Vec::new());
+        assert_eq!(
+            b"read and written",
+            &*sink.read_from(&mut bytes).unwrap().unwrap()
+        );
+    }
+
+    #[test]
+    fn write_sink_error() {
+        let mut sink = WriteSink::new(recorder(5));
+        sink.process("four".to_tendril());
+        sink.process("fails".to_tendril());
+        sink.process("a".to_tendril());
+        assert_eq!(1, sink.get_ref().writes.len());
+        let err = sink.finish().err().unwrap();
+        assert_eq!("out of space", err.to_string());
+    }
+
+    #[test]
+    fn write_sink_line_buffered() {
+        let mut sink = WriteSink::line_buffered(recorder(100));
+        sink.process("partial ".to_tendril());
+        sink.process("line".to_tendril());
+        assert!(sink.get_ref().writes.is_empty());
+        sink.process(" done\nnext\nand ".to_tendril());
+        assert_eq!(
+            vec![b"partial line".to_vec(), b" done\nnext\n".to_vec()],
+            sink.get_ref().writes
+        );
+        assert_eq!(1, sink.get_ref().flushes);
+        let w = sink.finish().unwrap();
+        assert_eq!(b"and ".to_vec(), w.writes[2]);
+        assert_eq!(2, w.flushes);
+    }
+
+    /// Collects text, and fails once it would hold more than `limit` bytes.
+    struct Limited {
+        text: String,
+        limit: usize,
+        calls: usize,
+    }
+
+    impl TryTendrilSink<fmt::UTF8> for Limited {
+        type Error = io::Error;
+
+        fn process(&mut self, t: Tendril<fmt::UTF8>) -> io::Result<()> {
+            self.calls += 1;
+            if self.text.len() + t.len() > self.limit {
+                return Err(io::Error::new(io::ErrorKind::Other, "too long"));
+            }
+            self.text.push_str(&t);
+            Ok(())
+        }
+
+        fn error(&mut self, _desc: Cow<'static, str>) {}
+
+        type Output = String;
+
+        fn finish(self) -> io::Result<String> {
+            Ok(self.text)
+        }
+    }
+
+    fn limited(limit: usize) -> Limited {
+        Limited {
+            text: String::new(),
+            limit,
+            calls: 0,
+        }
+    }
+
+    #[test]
+    fn try_sink() {
+        assert_eq!("abc", limited(5).from_iter(vec!["a", "b", "c"]).unwrap());
+        assert_eq!("abc", limited(5).one("abc").unwrap());
+        let err = limited(2).from_iter(vec!["a", "b", "c"]).unwrap_err();
+        assert_eq!("too long", err.to_string());
+
+        let mut sink = TryAdapter::new(Accumulate::<NonAtomic>::new());
+        TryTendrilSink::process(&mut sink, "x".to_tendril()).unwrap();
+        let (tendrils, _) = TryTendrilSink::finish(sink).unwrap();
+        assert_eq!(1, tendrils.len());
+    }
+
+    #[test]
+    fn try_decoder() {
+        let mut decoder = Utf8LossyDecoder::new(limited(6));
+        TryTendrilSink::process(&mut decoder, b"abc\xE2".to_tendril()).unwrap();
+        assert_eq!("abc", decoder.inner_sink.text);
+        let err = TryTendrilSink::process(&mut decoder, b"\x82\xACdef".to_tendril());
+        assert!(err.is_err());
+        assert_eq!(2, decoder.inner_sink.calls);
+
+        // Reading stops at the first error.
+        let mut bytes: &[u8] = &[b'a'; 10000];
+        let decoder = Utf8LossyDecoder::new(limited(100));
+        assert!(decoder.read_from(&mut bytes).is_err());
+        assert_eq!(10000 - 4096, bytes.len());
+
+        let mut bytes: &[u8] = b"caf\xC3\xA9";
+        let decoder = Utf8LossyDecoder::new(limited(100));
+        assert_eq!("caf\u{e9}", decoder.read_from(&mut bytes).unwrap());
+    }
+
+    #[test]
+    fn first_error() {
+        let mut sink = FirstError::new(limited(4));
+        sink.process("abc".to_tendril());
+        assert!(!sink.has_failed());
+        sink.process("def".to_tendril());
+        sink.process("g".to_tendril());
+        assert!(sink.has_failed());
+        assert_eq!(2, sink.inner_sink.calls);
+        assert!(sink.finish().is_err());
+
+        let decoder = Utf8LossyDecoder::new(FirstError::new(limited(10)));
+        let text = decoder.from_iter(vec![b"caf\xC3".to_tendril(), b"\xA9".to_tendril()]);
+        assert_eq!("caf\u{e9}", text.unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "encoding_rs")]
+    fn try_decoder_encoding_rs() {
+        let decoder = LossyDecoder::new_encoding_rs(enc_rs::KOI8_U, limited(100));
+        let text = TryTendrilSink::one(decoder, b"\xfc\xce\xc5\xd2\xc7\xc9\xd1".to_tendril());
+        assert_eq!("Энергия", text.unwrap());
+
+        let decoder = LossyDecoder::new_encoding_rs(enc_rs::KOI8_U, limited(4));
+        let err = TryTendrilSink::one(decoder, b"\xfc\xce\xc5".to_tendril());
+        assert!(err.is_err());
+    }
 }
@@ -4,29 +4,40 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::borrow::Borrow;
-use std::cell::{Cell, UnsafeCell};
-use std::cmp::Ordering;
-use std::default::Default;
-use std::fmt as strfmt;
-use std::iter::FromIterator;
-use std::marker::PhantomData;
-use std::num::NonZeroUsize;
-use std::ops::{Deref, DerefMut};
-use std::sync::atomic::Ordering as AtomicOrdering;
-use std::sync::atomic::{self, AtomicUsize};
-use std::{hash, io, mem, ptr, str, u32};
+use alloc::string::String;
+use core::borrow::Borrow;
+use core::cell::{Cell, UnsafeCell};
+use core::cmp::Ordering;
+use core::default::Default;
+use core::fmt as strfmt;
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+use core::num::NonZeroUsize;
+use core::ops::{Deref, DerefMut};
+#[cfg(feature = "atomic")]
+use core::sync::atomic::Ordering as AtomicOrdering;
+#[cfg(feature = "atomic")]
+use core::sync::atomic::{self, AtomicUsize};
+use core::{hash, mem, ptr, str, u32};
+#[cfg(feature = "std")]
+use std::io;
 
 #[cfg(feature = "encoding")]
 use encoding::{self, DecoderTrap, EncoderTrap, EncodingRef};
 
 use buf32::{self, Buf32};
+use byteset::ByteSet;
 use fmt::imp::Fixup;
 use fmt::{self, Slice};
+#[cfg(all(unix, feature = "mmap"))]
+use mmap;
+#[cfg(feature = "std")]
+use pool::{self, TendrilPool};
+use transcode::Transcode;
 use util::{copy_and_advance, copy_lifetime, copy_lifetime_mut, unsafe_slice, unsafe_slice_mut};
 use OFLOW;
 
-const MAX_INLINE_LEN: usize = 8;
+pub const MAX_INLINE_LEN: usize = 8;
 const MAX_INLINE_TAG: usize = 0xF;
 const EMPTY_TAG: usize = 0xF;
 
@@ -58,6 +69,12 @@ pub unsafe trait Atomicity: 'static {
     #[doc(hidden)]
     fn decrement(&self) -> usize;
 
+    #[doc(hidden)]
+    fn load(&self) -> usize;
+
+    #[doc(hidden)]
+    fn increment_if_nonzero(&self) -> bool;
+
     #[doc(hidden)]
     fn fence_acquire();
 }
@@ -91,6 +108,22 @@ unsafe impl Atomicity for NonAtomic {
         value
     }
 
+    #[inline]
+    fn load(&self) -> usize {
+        self.0.get()
+    }
+
+    #[inline]
+    fn increment_if_nonzero(&self) -> bool {
+        match self.0.get() {
+            0 => false,
+            value => {
+                self.0.set(value.checked_add(1).expect(OFLOW));
+                true
+            }
+        }
+    }
+
     #[inline]
     fn fence_acquire() {}
 }
@@ -101,8 +134,13 @@ unsafe impl Atomicity for NonAtomic {
 /// `Tendril<F, Atomic>` thus implements`Send`.
 ///
 /// This is akin to using `Arc` for reference counting.
+///
+/// Requires the `atomic` feature, which is on by default, since some
+/// targets have no atomic compare-and-swap.
+#[cfg(feature = "atomic")]
 pub struct Atomic(AtomicUsize);
 
+#[cfg(feature = "atomic")]
 unsafe impl Atomicity for Atomic {
     #[inline]
     fn new() -> Self {
@@ -120,16 +158,52 @@ unsafe impl Atomicity for Atomic {
         self.0.fetch_sub(1, AtomicOrdering::Release)
     }
 
+    #[inline]
+    fn load(&self) -> usize {
+        self.0.load(AtomicOrdering::Acquire)
+    }
+
+    #[inline]
+    fn increment_if_nonzero(&self) -> bool {
+        let mut value = self.0.load(AtomicOrdering::Relaxed);
+        loop {
+            if value == 0 {
+                return false;
+            }
+            match self.0.compare_exchange_weak(
+                value,
+                value + 1,
+                AtomicOrdering::Acquire,
+                AtomicOrdering::Relaxed,
+            ) {
+                Ok(_) => return true,
+                Err(actual) => value = actual,
+            }
+        }
+    }
+
     #[inline]
     fn fence_acquire() {
         atomic::fence(AtomicOrdering::Acquire);
     }
 }
 
+/// The buffer came from a `TendrilPool`, whose address is stored in the
+/// header-sized slot just before the header.
+pub const POOLED: u32 = 1;
+
+/// The buffer is a memory-mapped file, and the header sits at the end of
+/// the page just before the mapping.
+#[cfg(all(unix, feature = "mmap"))]
+pub const MAPPED: u32 = 2;
+
 #[repr(C)] // Preserve field order for cross-atomicity transmutes
-struct Header<A: Atomicity> {
+pub struct Header<A: Atomicity> {
     refcount: A,
+    // Counts `WeakTendril`s, plus one for all the strong references together.
+    weak: A,
     cap: u32,
+    flags: u32,
 }
 
 impl<A> Header<A>
@@ -140,9 +214,44 @@ where
     unsafe fn new() -> Header<A> {
         Header {
             refcount: A::new(),
+            weak: A::new(),
             cap: 0,
+            flags: 0,
+        }
+    }
+}
+
+/// Free a buffer, or hand it back to the pool it came from.
+#[inline]
+unsafe fn destroy_buf<A>(buf: Buf32<Header<A>>)
+where
+    A: Atomicity,
+{
+    #[cfg(all(unix, feature = "mmap"))]
+    {
+        if (*buf.ptr).flags & MAPPED != 0 {
+            return mmap::release(buf);
         }
     }
+    #[cfg(feature = "std")]
+    {
+        if (*buf.ptr).flags & POOLED != 0 {
+            return pool::release(buf);
+        }
+    }
+    buf.destroy();
+}
+
+/// Drop one weak reference to a buffer, freeing it if that was the last.
+#[inline]
+unsafe fn release_weak<A>(buf: Buf32<Header<A>>)
+where
+    A: Atomicity,
+{
+    if (*buf.ptr).weak.decrement() == 1 {
+        A::fence_acquire();
+        destroy_buf(buf);
+    }
 }
 
 /// Errors that can occur when slicing a `Tendril`.
@@ -254,10 +363,14 @@ where
                 let header = self.header();
                 if (*header).refcount.decrement() == 1 {
                     A::fence_acquire();
-                    buf.destroy();
+                    // The contents are plain bytes, so there is nothing to
+                    // drop; give up the weak reference held by the strong
+                    // ones, which may free the buffer.
+                    release_weak(buf);
                 }
             } else {
-                buf.destroy();
+                // An owned buffer never has weak references.
+                destroy_buf(buf);
             }
         }
     }
@@ -604,6 +717,19 @@ where
         }
     }
 
+    /// Get the number of bytes of storage backing the `Tendril`.
+    ///
+    /// For a shared buffer this is the capacity of the whole buffer, which
+    /// may be much larger than the `Tendril` itself.
+    #[inline]
+    pub fn capacity(&self) -> u32 {
+        if self.ptr.get().get() <= MAX_INLINE_TAG {
+            MAX_INLINE_LEN as u32
+        } else {
+            unsafe { self.assume_buf().0.cap }
+        }
+    }
+
     /// Is the backing buffer shared?
     #[inline]
     pub fn is_shared(&self) -> bool {
@@ -620,6 +746,57 @@ where
         (n > MAX_INLINE_TAG) && (n == other.ptr.get().get())
     }
 
+    /// Get the address of the shared buffer, and this tendril's offset
+    /// into it. `None` if the buffer is inline or not shared.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn shared_buffer(&self) -> Option<(usize, u32)> {
+        if self.is_shared() {
+            unsafe { Some((self.header() as usize, self.aux())) }
+        } else {
+            None
+        }
+    }
+
+    /// Make a weak reference to this tendril's contents.
+    ///
+    /// A heap buffer becomes shared, so that it will not be modified in
+    /// place while the `WeakTendril` exists. Inline tendrils have no buffer
+    /// to refer to, so the `WeakTendril` keeps a copy.
+    #[inline]
+    pub fn downgrade(&self) -> WeakTendril<F, A> {
+        unsafe {
+            if self.ptr.get().get() <= MAX_INLINE_TAG {
+                return WeakTendril {
+                    inner: WeakInner::Inline(ptr::read(self)),
+                };
+            }
+
+            self.make_buf_shared();
+            let header = self.header();
+            (*header).weak.increment();
+            WeakTendril {
+                inner: WeakInner::Heap {
+                    header,
+                    offset: self.aux(),
+                    len: self.len32(),
+                },
+            }
+        }
+    }
+
+    /// Number of tendrils holding the backing buffer, or `None` if the
+    /// buffer is inline or owned.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) fn buffer_refcount(&self) -> Option<usize> {
+        if self.is_shared() {
+            Some(unsafe { (*self.header()).refcount.load() })
+        } else {
+            None
+        }
+    }
+
     /// Truncate to length 0 without discarding any owned storage.
     #[inline]
     pub fn clear(&mut self) {
@@ -637,6 +814,60 @@ where
         }
     }
 
+    /// Reallocate an owned buffer down to the length of the `Tendril`.
+    ///
+    /// Short strings move to inline storage. Shared buffers are left alone,
+    /// because other tendrils may be using the rest of the buffer; see
+    /// `compact_if_wasteful` for those.
+    #[inline]
+    pub fn shrink_to_fit(&mut self) {
+        let p = self.ptr.get().get();
+        if p <= MAX_INLINE_TAG || (p & 1) == 1 {
+            return;
+        }
+
+        let len = self.len32();
+        unsafe {
+            if len <= MAX_INLINE_LEN as u32 {
+                *self = Tendril::inline(self.as_byte_slice());
+            } else if (*self.header()).flags & POOLED != 0 {
+                // Pool buffers all have the same size, so give this one back.
+                *self = Tendril::owned_copy(self.as_byte_slice());
+            } else {
+                let mut buf = self.assume_buf().0;
+                buf.shrink(len);
+                self.ptr.set(NonZeroUsize::new_unchecked(buf.ptr as usize));
+                self.set_aux(buf.cap);
+            }
+        }
+    }
+
+    /// Stop pinning a buffer which is much larger than this `Tendril`.
+    ///
+    /// If the capacity is more than `ratio` times the length, a shared
+    /// `Tendril` is copied into its own buffer (or inline storage) and an
+    /// owned one is shrunk to fit. Returns `true` if the storage changed.
+    #[inline]
+    pub fn compact_if_wasteful(&mut self, ratio: u32) -> bool {
+        let p = self.ptr.get().get();
+        if p <= MAX_INLINE_TAG {
+            return false;
+        }
+
+        let cap = self.capacity();
+        if (cap as u64) <= (self.len32() as u64) * (ratio as u64) {
+            return false;
+        }
+
+        if (p & 1) == 1 {
+            *self = unsafe { Tendril::from_byte_slice_without_validating(self.as_byte_slice()) };
+            true
+        } else {
+            self.shrink_to_fit();
+            self.capacity() != cap
+        }
+    }
+
     /// Build a `Tendril` by copying a byte slice, if it conforms to the format.
     #[inline]
     pub fn try_from_byte_slice(x: &[u8]) -> Result<Tendril<F, A>, ()> {
@@ -748,8 +979,14 @@ where
 
     /// Push some bytes onto the end of the `Tendril`, if they conform to the
     /// format.
+    ///
+    /// If the format isn't `CONCAT_SAFE`, the whole buffer is checked after
+    /// the push instead, and left as it was if that fails.
     #[inline]
     pub fn try_push_bytes(&mut self, buf: &[u8]) -> Result<(), ()> {
+        if !F::CONCAT_SAFE {
+            return self.try_push_bytes_revalidating(buf);
+        }
         match F::validate(buf) {
             true => unsafe {
                 self.push_bytes_without_validating(buf);
@@ -759,9 +996,39 @@ where
         }
     }
 
+    // `mem::take` needs Rust 1.40.
+    #[allow(clippy::mem_replace_with_default)]
+    fn try_push_bytes_revalidating(&mut self, buf: &[u8]) -> Result<(), ()> {
+        // `Bytes` has no fixups, so popping the pushed bytes undoes the push.
+        let len = self.len32();
+        let mut t = mem::replace(self, Tendril::new()).into_bytes();
+        t.push_slice(buf);
+        match t.try_reinterpret() {
+            Ok(t) => {
+                *self = t;
+                Ok(())
+            }
+            Err(mut t) => {
+                let pushed = t.len32() - len;
+                t.pop_back(pushed);
+                *self = unsafe { t.reinterpret_without_validating() };
+                Err(())
+            }
+        }
+    }
+
     /// Push another `Tendril` onto the end of this one.
+    ///
+    /// Panics if the format isn't `CONCAT_SAFE` and the result fails
+    /// validation.
     #[inline]
     pub fn push_tendril(&mut self, other: &Tendril<F, A>) {
+        if !F::CONCAT_SAFE {
+            return self
+                .try_push_bytes_revalidating(other.as_byte_slice())
+                .expect("tendril: concatenation fails validation");
+        }
+
         let new_len = self.len32().checked_add(other.len32()).expect(OFLOW);
 
         unsafe {
@@ -804,7 +1071,7 @@ where
 
         unsafe {
             let byte_slice = unsafe_slice(self.as_byte_slice(), offset as usize, length as usize);
-            if !F::validate_subseq(byte_slice) {
+            if !F::validate_offset(offset as usize) || !F::validate_subseq(byte_slice) {
                 return Err(SubtendrilError::ValidationFailed);
             }
 
@@ -1063,6 +1330,11 @@ where
     unsafe fn make_owned_with_capacity(&mut self, cap: u32) {
         self.make_owned();
         let mut buf = self.assume_buf().0;
+        if cap > buf.cap && (*buf.ptr).flags & POOLED != 0 {
+            // Pool buffers can't be reallocated; move out into our own.
+            *self = Tendril::owned_copy_with_capacity(self.as_byte_slice(), cap);
+            buf = self.assume_buf().0;
+        }
         buf.grow(cap);
         self.ptr.set(NonZeroUsize::new_unchecked(buf.ptr as usize));
         self.set_aux(buf.cap);
@@ -1124,13 +1396,64 @@ where
 
     #[inline]
     unsafe fn owned_copy(x: &[u8]) -> Tendril<F, A> {
+        Tendril::owned_copy_with_capacity(x, x.len() as u32)
+    }
+
+    #[inline]
+    unsafe fn owned_copy_with_capacity(x: &[u8], cap: u32) -> Tendril<F, A> {
         let len32 = x.len() as u32;
-        let mut b = Buf32::with_capacity(len32, Header::new());
+        debug_assert!(len32 <= cap);
+        let mut b = Buf32::with_capacity(cap, Header::new());
         ptr::copy_nonoverlapping(x.as_ptr(), b.data_ptr(), x.len());
         b.len = len32;
         Tendril::owned(b)
     }
 
+    /// Make an empty owned `Tendril` in a buffer from a `TendrilPool`.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub(crate) unsafe fn pooled(header: *mut Header<A>, cap: u32) -> Tendril<F, A> {
+        ptr::write(
+            header,
+            Header {
+                refcount: A::new(),
+                weak: A::new(),
+                cap: 0,
+                flags: POOLED,
+            },
+        );
+        Tendril::owned(Buf32 {
+            ptr: header,
+            len: 0,
+            cap,
+        })
+    }
+
+    /// Make a shared `Tendril` over a memory-mapped file, whose header is
+    /// just before the mapped bytes.
+    #[cfg(all(unix, feature = "mmap"))]
+    #[inline]
+    pub(crate) unsafe fn mapped(header: *mut Header<A>, len: u32) -> Tendril<F, A> {
+        ptr::write(
+            header,
+            Header {
+                refcount: A::new(),
+                weak: A::new(),
+                cap: len,
+                flags: MAPPED,
+            },
+        );
+        Tendril::shared(
+            Buf32 {
+                ptr: header,
+                len,
+                cap: len,
+            },
+            0,
+            len,
+        )
+    }
+
     #[inline]
     unsafe fn shared(buf: Buf32<Header<A>>, off: u32, len: u32) -> Tendril<F, A> {
         Tendril {
@@ -1163,7 +1486,7 @@ where
     // There's no need to worry about locking on an atomic Tendril, because it makes it unique as
     // soon as you do that.
     #[inline]
-    fn as_mut_byte_slice<'a>(&'a mut self) -> &'a mut [u8] {
+    pub(crate) fn as_mut_byte_slice<'a>(&'a mut self) -> &'a mut [u8] {
         unsafe {
             match self.ptr.get().get() {
                 EMPTY_TAG => &mut [],
@@ -1257,6 +1580,118 @@ where
     }
 }
 
+/// A reference to the contents of a `Tendril` which does not keep its
+/// buffer alive.
+///
+/// Made by `Tendril::downgrade`. Use `upgrade` to get the `Tendril` back,
+/// as long as some other `Tendril` still holds the buffer.
+pub struct WeakTendril<F, A = NonAtomic>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    inner: WeakInner<F, A>,
+}
+
+enum WeakInner<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    Inline(Tendril<F, A>),
+    Heap {
+        header: *mut Header<A>,
+        offset: u32,
+        len: u32,
+    },
+}
+
+unsafe impl<F, A> Send for WeakTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity + Sync,
+{
+}
+
+impl<F, A> WeakTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Get a `Tendril` with the referenced contents, or `None` if every
+    /// `Tendril` holding the buffer has been dropped.
+    ///
+    /// A `WeakTendril` of an inline tendril always upgrades to a copy.
+    #[inline]
+    pub fn upgrade(&self) -> Option<Tendril<F, A>> {
+        match self.inner {
+            WeakInner::Inline(ref t) => Some(t.clone()),
+            WeakInner::Heap {
+                header,
+                offset,
+                len,
+            } => unsafe {
+                if !(*header).refcount.increment_if_nonzero() {
+                    return None;
+                }
+                let buf = Buf32 {
+                    ptr: header,
+                    len: offset + len,
+                    cap: (*header).cap,
+                };
+                Some(Tendril::shared(buf, offset, len))
+            },
+        }
+    }
+}
+
+impl<F, A> Clone for WeakTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn clone(&self) -> WeakTendril<F, A> {
+        let inner = match self.inner {
+            WeakInner::Inline(ref t) => WeakInner::Inline(t.clone()),
+            WeakInner::Heap {
+                header,
+                offset,
+                len,
+            } => {
+                unsafe {
+                    (*header).weak.increment();
+                }
+                WeakInner::Heap {
+                    header,
+                    offset,
+                    len,
+                }
+            }
+        };
+        WeakTendril { inner }
+    }
+}
+
+impl<F, A> Drop for WeakTendril<F, A>
+where
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn drop(&mut self) {
+        if let WeakInner::Heap { header, .. } = self.inner {
+            unsafe {
+                release_weak(Buf32 {
+                    ptr: header,
+                    len: 0,
+                    cap: (*header).cap,
+                });
+            }
+        }
+    }
+}
+
 /// `Tendril`-related methods for Rust slices.
 pub trait SliceExt<F>: fmt::Slice
 where
@@ -1359,13 +1794,78 @@ where
     }
 }
 
+impl<F, A> Tendril<F, A>
+where
+    F: fmt::AsciiCompatible,
+    A: Atomicity,
+{
+    /// Remove and return the run of bytes at the front of the `Tendril`
+    /// which are all in `set`.
+    ///
+    /// The run is scanned many bytes at a time where the CPU allows it,
+    /// and shares the buffer like `subtendril`. Returns `None` if the
+    /// first byte is not in `set`, or the `Tendril` is empty.
+    ///
+    /// In a format with multi-byte characters, such as `UTF8`, `set` must
+    /// hold either all of the bytes from 0x80 up or none of them, so that
+    /// the run can't end inside a character. Panics otherwise.
+    #[inline]
+    pub fn pop_front_byte_run(&mut self, set: &ByteSet) -> Option<Tendril<F, A>> {
+        if !F::SINGLE_BYTE && !set.is_uniform_above_ascii() {
+            panic!("byte set would split a multi-byte character");
+        }
+        let n = set.span(self.as_byte_slice());
+        unsafe { self.pop_front_run(n) }
+    }
+
+    /// Remove and return the run of ASCII characters at the front of the
+    /// `Tendril` which are all in `set`.
+    ///
+    /// This is a faster `pop_front_char_run` for simple character classes.
+    /// Bytes in `set` from 0x80 up are ignored, so the run always ends on
+    /// a character boundary. Returns `None` if the first character is not
+    /// in `set`, or the `Tendril` is empty.
+    #[inline]
+    pub fn pop_front_while_ascii(&mut self, set: &ByteSet) -> Option<Tendril<F, A>> {
+        let n = set.ascii_span(self.as_byte_slice());
+        unsafe { self.pop_front_run(n) }
+    }
+
+    /// Split off the first `n` bytes, which must end on a character
+    /// boundary, as `pop_front_char_run` does.
+    #[inline]
+    unsafe fn pop_front_run(&mut self, n: usize) -> Option<Tendril<F, A>> {
+        if n == 0 {
+            return None;
+        }
+        if n == self.len32() as usize {
+            let t = self.clone();
+            self.clear();
+            return Some(t);
+        }
+        let t = self.unsafe_subtendril(0, n as u32);
+        self.unsafe_pop_front(n as u32);
+        Some(t)
+    }
+}
+
 /// Extension trait for `io::Read`.
+#[cfg(feature = "std")]
 pub trait ReadExt: io::Read {
     fn read_to_tendril<A>(&mut self, buf: &mut Tendril<fmt::Bytes, A>) -> io::Result<usize>
     where
         A: Atomicity;
+
+    fn read_to_tendril_pooled<A>(
+        &mut self,
+        buf: &mut Tendril<fmt::Bytes, A>,
+        pool: &TendrilPool,
+    ) -> io::Result<usize>
+    where
+        A: Atomicity;
 }
 
+#[cfg(feature = "std")]
 impl<T> ReadExt for T
 where
     T: io::Read,
@@ -1414,8 +1914,28 @@ where
         buf.pop_back(buf_len - (len as u32));
         ret
     }
+
+    /// Read all bytes until EOF, starting in a buffer from `pool` if `buf`
+    /// is empty.
+    ///
+    /// If the data outgrows the pool buffer, it moves to a heap buffer of
+    /// its own and the pool buffer is given back.
+    fn read_to_tendril_pooled<A>(
+        &mut self,
+        buf: &mut Tendril<fmt::Bytes, A>,
+        pool: &TendrilPool,
+    ) -> io::Result<usize>
+    where
+        A: Atomicity,
+    {
+        if buf.len32() == 0 {
+            *buf = pool.get();
+        }
+        self.read_to_tendril(buf)
+    }
 }
 
+#[cfg(feature = "std")]
 impl<A> io::Write for Tendril<fmt::Bytes, A>
 where
     A: Atomicity,
@@ -1455,7 +1975,7 @@ where
 
     #[inline]
     fn writer_hint(&mut self, additional: usize) {
-        self.reserve(::std::cmp::min(u32::MAX as usize, additional) as u32);
+        self.reserve(::core::cmp::min(u32::MAX as usize, additional) as u32);
     }
 }
 
@@ -1474,7 +1994,7 @@ where
         &self,
         encoding: EncodingRef,
         trap: DecoderTrap,
-    ) -> Result<Tendril<fmt::UTF8, A>, ::std::borrow::Cow<'static, str>> {
+    ) -> Result<Tendril<fmt::UTF8, A>, ::alloc::borrow::Cow<'static, str>> {
         let mut ret = Tendril::new();
         encoding.decode_to(&*self, trap, &mut ret).map(|_| ret)
     }
@@ -1496,6 +2016,26 @@ where
     }
 }
 
+macro_rules! utf16_push_char {
+    ($($fmt:ident)*) => {$(
+        impl<A> Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            /// Push a character onto the end.
+            #[inline]
+            pub fn push_char(&mut self, c: char) {
+                <fmt::$fmt as fmt::CharFormat>::encode_char(c, |b| unsafe {
+                    self.push_bytes_without_validating(b);
+                })
+                .unwrap()
+            }
+        }
+    )*};
+}
+
+utf16_push_char!(UTF16LE UTF16BE);
+
 impl<A> strfmt::Display for Tendril<fmt::UTF8, A>
 where
     A: Atomicity,
@@ -1546,10 +2086,63 @@ where
 
     #[inline]
     fn writer_hint(&mut self, additional: usize) {
-        self.reserve(::std::cmp::min(u32::MAX as usize, additional) as u32);
+        self.reserve(::core::cmp::min(u32::MAX as usize, additional) as u32);
     }
 }
 
+impl<A> Tendril<fmt::UTF8, A>
+where
+    A: Atomicity,
+{
+    /// Convert to CESU-8.
+    ///
+    /// When there are no characters outside the Basic Multilingual Plane,
+    /// the bytes are the same, and the buffer is shared rather than copied.
+    #[inline]
+    pub fn to_cesu8(&self) -> Tendril<fmt::CESU8, A> {
+        if self.as_byte_slice().iter().all(|&b| b < 0xF0) {
+            return unsafe { self.clone().reinterpret_without_validating() };
+        }
+        self.transcode().unwrap()
+    }
+
+    /// Convert to Modified UTF-8.
+    ///
+    /// When there are no NULs and no characters outside the Basic
+    /// Multilingual Plane, the bytes are the same, and the buffer is shared
+    /// rather than copied.
+    #[inline]
+    pub fn to_mutf8(&self) -> Tendril<fmt::MUTF8, A> {
+        if self.as_byte_slice().iter().all(|&b| b != 0 && b < 0xF0) {
+            return unsafe { self.clone().reinterpret_without_validating() };
+        }
+        self.transcode().unwrap()
+    }
+}
+
+macro_rules! cesu8_to_utf8 {
+    ($($fmt:ident)*) => {$(
+        impl<A> Tendril<fmt::$fmt, A>
+        where
+            A: Atomicity,
+        {
+            /// Convert to UTF-8.
+            ///
+            /// When the text is already valid UTF-8, the buffer is shared
+            /// rather than copied.
+            #[inline]
+            pub fn to_utf8(&self) -> Tendril<fmt::UTF8, A> {
+                if str::from_utf8(self.as_byte_slice()).is_ok() {
+                    return unsafe { self.clone().reinterpret_without_validating() };
+                }
+                self.transcode().unwrap()
+            }
+        }
+    )*};
+}
+
+cesu8_to_utf8!(CESU8 MUTF8);
+
 impl<A> Tendril<fmt::UTF8, A>
 where
     A: Atomicity,
@@ -1564,7 +2157,7 @@ where
         &self,
         encoding: EncodingRef,
         trap: EncoderTrap,
-    ) -> Result<Tendril<fmt::Bytes, A>, ::std::borrow::Cow<'static, str>> {
+    ) -> Result<Tendril<fmt::Bytes, A>, ::alloc::borrow::Cow<'static, str>> {
         let mut ret = Tendril::new();
         encoding.encode_to(&*self, trap, &mut ret).map(|_| ret)
     }
@@ -1588,7 +2181,7 @@ where
     /// Helper for the `format_tendril!` macro.
     #[inline]
     pub fn format(args: strfmt::Arguments) -> Tendril<fmt::UTF8, A> {
-        use std::fmt::Write;
+        use core::fmt::Write;
         let mut output: Tendril<fmt::UTF8, A> = Tendril::new();
         let _ = write!(&mut output, "{}", args);
         output
@@ -1661,15 +2254,29 @@ mod bench;
 
 #[cfg(test)]
 mod test {
-    use super::{
-        Atomic, ByteTendril, Header, NonAtomic, ReadExt, SendTendril, SliceExt, StrTendril, Tendril,
-    };
+    use super::{ByteSet, ByteTendril, Header, NonAtomic, SliceExt, StrTendril, Tendril};
+    #[cfg(feature = "atomic")]
+    use super::Atomic;
+    #[cfg(all(feature = "std", feature = "atomic"))]
+    use super::WeakTendril;
+    #[cfg(feature = "std")]
+    use super::{ReadExt, SendTendril};
+    use alloc::format;
+    use core::{iter, mem};
     use fmt;
-    use std::iter;
+    #[cfg(feature = "std")]
     use std::thread;
 
+    #[cfg(feature = "std")]
     fn assert_send<T: Send>() {}
 
+    /// The capacity of a buffer shrunk to `n` bytes, which is rounded up
+    /// to a whole number of header-sized units.
+    fn shrunk_capacity(n: u32) -> u32 {
+        let h = mem::size_of::<Header<NonAtomic>>() as u32;
+        (n + h + h - 1) / h * h - h
+    }
+
     #[test]
     fn smoke_test() {
         assert_eq!("", &*"".to_tendril());
@@ -1683,7 +2290,7 @@ mod test {
 
     #[test]
     fn assert_sizes() {
-        use std::mem;
+        use core::mem;
         struct EmptyWithDrop;
         impl Drop for EmptyWithDrop {
             fn drop(&mut self) {}
@@ -1706,9 +2313,10 @@ mod test {
         // assert_eq!(correct, mem::size_of::<Option<StrTendril>>());
 
         assert_eq!(
-            mem::size_of::<*const ()>() * 2,
-            mem::size_of::<Header<Atomic>>(),
+            2 * mem::size_of::<*const ()>() + 8,
+            mem::size_of::<Header<NonAtomic>>(),
         );
+        #[cfg(feature = "atomic")]
         assert_eq!(
             mem::size_of::<Header<Atomic>>(),
             mem::size_of::<Header<NonAtomic>>(),
@@ -1914,11 +2522,75 @@ mod test {
         assert_eq!("abcxyz", &*t);
     }
 
+    #[test]
+    fn capacity() {
+        assert_eq!(8, "".to_tendril().capacity());
+        assert_eq!(8, "foo".to_tendril().capacity());
+
+        let mut t = StrTendril::with_capacity(100);
+        assert!(t.capacity() >= 100);
+        t.push_slice("a long enough string");
+        assert!(t.capacity() >= 100);
+
+        let u = t.subtendril(2, 10);
+        assert_eq!(t.capacity(), u.capacity());
+    }
+
+    #[test]
+    fn shrink_to_fit() {
+        let mut t = StrTendril::with_capacity(1000);
+        t.push_slice("a long enough string");
+        t.shrink_to_fit();
+        assert_eq!(shrunk_capacity(20), t.capacity());
+        assert_eq!("a long enough string", &*t);
+
+        t.pop_back(5);
+        assert!(t.is_shared());
+        t.shrink_to_fit();
+        assert!(t.is_shared());
+
+        let mut t = StrTendril::with_capacity(1000);
+        t.push_slice("short");
+        t.shrink_to_fit();
+        assert_eq!(8, t.capacity());
+        assert_eq!("short", &*t);
+    }
+
+    #[test]
+    fn compact_if_wasteful() {
+        let big: StrTendril = iter::repeat('x').take(1000).collect();
+        let mut t = big.subtendril(10, 20);
+        assert!(!t.compact_if_wasteful(100));
+        assert!(t.is_shared_with(&big));
+
+        assert!(t.compact_if_wasteful(4));
+        assert!(!t.is_shared());
+        assert_eq!(20, t.capacity());
+        assert_eq!(&big[10..30], &*t);
+        assert!(!t.compact_if_wasteful(4));
+
+        let mut t = big.subtendril(500, 9);
+        assert!(t.compact_if_wasteful(4));
+        assert_eq!(16, t.capacity());
+        assert_eq!("xxxxxxxxx", &*t);
+
+        let mut t = StrTendril::with_capacity(1000);
+        t.push_slice("a long enough string");
+        assert!(t.compact_if_wasteful(4));
+        assert_eq!(shrunk_capacity(20), t.capacity());
+        assert_eq!("a long enough string", &*t);
+    }
+
     #[test]
     fn wtf8() {
         assert!(Tendril::<fmt::WTF8>::try_from_byte_slice(b"\xED\xA0\xBD").is_ok());
         assert!(Tendril::<fmt::WTF8>::try_from_byte_slice(b"\xED\xB2\xA9").is_ok());
         assert!(Tendril::<fmt::WTF8>::try_from_byte_slice(b"\xED\xA0\xBD\xED\xB2\xA9").is_err());
+        // ASCII between surrogates keeps them from pairing.
+        let mut t = Tendril::<fmt::WTF8>::try_from_byte_slice(b"\xED\xA0\xBDa").unwrap();
+        assert!(t.try_push_bytes(b"long run of ASCII text\xED\xB2\xA9").is_ok());
+        assert!(t.try_push_bytes(b"long run of ASCII text\xED").is_err());
+        assert!(t.try_push_bytes(b"long run of ASCII text\xFF").is_err());
 
         let t: Tendril<fmt::WTF8> =
             Tendril::try_from_byte_slice(b"\xED\xA0\xBD\xEA\x99\xAE").unwrap();
@@ -2161,6 +2833,220 @@ mod test {
         assert_eq!(b"x\0\xa0", t.as_byte_slice());
     }
 
+    #[test]
+    fn pop_front_byte_run() {
+        let space = ByteSet::from_bytes(b" \t\xa0");
+        let mut t = b" \t\xa0word\xa0 and some more words".to_tendril();
+        assert_eq!(b" \t\xa0", &*t.pop_front_byte_run(&space).unwrap());
+        assert!(t.pop_front_byte_run(&space).is_none());
+        let word = t.pop_front_byte_run(&space.complement()).unwrap();
+        assert_eq!(b"word", &*word);
+        assert_eq!(b"\xa0 ", &*t.pop_front_byte_run(&space).unwrap());
+
+        let rest = t.clone();
+        let run = t.pop_front_byte_run(&ByteSet::from_fn(|_| true)).unwrap();
+        assert_eq!(b"and some more words", &*run);
+        assert!(run.is_shared_with(&rest));
+        assert!(t.is_empty());
+        assert!(t.pop_front_byte_run(&space).is_none());
+
+        let text = ByteSet::from_bytes(b"<&\r\0").complement();
+        let mut t = "\u{d55c}\u{ad6d}\u{c5b4} text<br>".to_tendril();
+        let run = t.pop_front_byte_run(&text).unwrap();
+        assert_eq!("\u{d55c}\u{ad6d}\u{c5b4} text", &*run);
+        assert_eq!("<br>", &*t);
+        assert!(t.pop_front_byte_run(&text).is_none());
+
+        let mut t: Tendril<fmt::Latin1> = b"caf\xe9 ".to_tendril().try_reinterpret().unwrap();
+        let run = t.pop_front_byte_run(&ByteSet::from_bytes(b"acf\xe9")).unwrap();
+        assert_eq!(b"caf\xe9", run.as_byte_slice());
+    }
+
+    #[test]
+    #[should_panic]
+    fn pop_front_byte_run_splits_char() {
+        let mut t = "caf\u{e9}".to_tendril();
+        t.pop_front_byte_run(&ByteSet::from_bytes(b"acf\xc3"));
+    }
+
+    #[test]
+    fn pop_front_while_ascii() {
+        let text = ByteSet::from_bytes(b"<&\r\0\xc3").complement();
+        let mut t = "caf\u{e9} noir<br>".to_tendril();
+        assert_eq!("caf", &*t.pop_front_while_ascii(&text).unwrap());
+        assert!(t.pop_front_while_ascii(&text).is_none());
+        assert_eq!(Some('\u{e9}'), t.pop_front_char());
+        assert_eq!(" noir", &*t.pop_front_while_ascii(&text).unwrap());
+        assert!(t.pop_front_while_ascii(&text).is_none());
+
+        let mut t = "an ASCII-only line of text".to_tendril();
+        let rest = t.clone();
+        let run = t.pop_front_while_ascii(&text).unwrap();
+        assert_eq!("an ASCII-only line of text", &*run);
+        assert!(run.is_shared_with(&rest));
+        assert!(t.is_empty());
+
+        let mut t: Tendril<fmt::WTF8> = b"ab\xed\xa0\x80cd".to_tendril().try_reinterpret().unwrap();
+        let run = t
+            .pop_front_while_ascii(&ByteSet::from_fn(|_| true))
+            .unwrap();
+        assert_eq!(b"ab", run.as_byte_slice());
+        assert_eq!(b"\xed\xa0\x80cd", t.as_byte_slice());
+    }
+
+    #[test]
+    fn utf16() {
+        fn mk(x: &[u8]) -> Tendril<fmt::UTF16LE> {
+            x.to_tendril().try_reinterpret().unwrap()
+        }
+
+        assert!(b"x".to_tendril().try_reinterpret::<fmt::UTF16LE>().is_err());
+        assert!(b"\x3D\xD8"
+            .to_tendril()
+            .try_reinterpret::<fmt::UTF16LE>()
+            .is_err());
+        assert!(b"\xA9\xDC"
+            .to_tendril()
+            .try_reinterpret::<fmt::UTF16LE>()
+            .is_err());
+        assert!(b"\x3D\xD8x\x00"
+            .to_tendril()
+            .try_reinterpret::<fmt::UTF16LE>()
+            .is_err());
+        assert!(b"\x3D\xD8"
+            .to_tendril()
+            .try_reinterpret::<fmt::WTF16LE>()
+            .is_ok());
+
+        let mut t = mk(b"x\x00\x3D\xD8\xA9\xDC\x6E\xA6");
+        assert!(t.try_subtendril(1, 2).is_err());
+        assert!(t.try_subtendril(2, 2).is_err());
+        assert!(t.try_subtendril(4, 4).is_err());
+        assert!(t.try_subtendril(2, 4).is_ok());
+        assert_eq!(Some('x'), t.pop_front_char());
+        assert_eq!(Some('\u{1f4a9}'), t.pop_front_char());
+        assert_eq!(Some('\u{a66e}'), t.pop_front_char());
+        assert_eq!(None, t.pop_front_char());
+
+        let mut t = Tendril::<fmt::UTF16LE>::new();
+        t.push_char('x');
+        t.push_char('\u{1f4a9}');
+        assert!(t.try_push_char('\u{a66e}').is_ok());
+        assert_eq!(b"x\x00\x3D\xD8\xA9\xDC\x6E\xA6", t.as_byte_slice());
+
+        let mut t = Tendril::<fmt::UTF16BE>::new();
+        t.push_char('x');
+        t.push_char('\u{1f4a9}');
+        assert_eq!(b"\x00x\xD8\x3D\xDC\xA9", t.as_byte_slice());
+        assert_eq!(Some('x'), t.pop_front_char());
+        assert_eq!(Some('\u{1f4a9}'), t.pop_front_char());
+
+        // Lone surrogates join up without a fixup.
+        let mut t: Tendril<fmt::WTF16BE> = b"\xD8\x3D".to_tendril().try_reinterpret().unwrap();
+        assert!(t.try_push_bytes(b"\xDC").is_err());
+        assert!(t.try_push_bytes(b"\xDC\xA9").is_ok());
+        assert!(t.try_reinterpret_view::<fmt::UTF16BE>().is_ok());
+        assert!(t.try_subtendril(1, 2).is_err());
+        assert!(t.try_subtendril(2, 2).is_ok());
+    }
+
+    #[test]
+    fn cesu8() {
+        fn mk(x: &[u8]) -> Tendril<fmt::CESU8> {
+            x.to_tendril().try_reinterpret().unwrap()
+        }
+
+        let poo = b"\xED\xA0\xBD\xED\xB2\xA9";
+        assert!(b"\xF0\x9F\x92\xA9"
+            .to_tendril()
+            .try_reinterpret::<fmt::CESU8>()
+            .is_err());
+        assert!(b"\xED\xA0\xBD"
+            .to_tendril()
+            .try_reinterpret::<fmt::CESU8>()
+            .is_err());
+        assert!(b"\xED\xB2\xA9"
+            .to_tendril()
+            .try_reinterpret::<fmt::CESU8>()
+            .is_err());
+        assert!(b"\xC0\x80"
+            .to_tendril()
+            .try_reinterpret::<fmt::CESU8>()
+            .is_err());
+
+        let mut t = mk(b"a\x00\xED\xA0\xBD\xED\xB2\xA9\xEA\x99\xAE");
+        assert!(t.try_subtendril(2, 3).is_err());
+        assert!(t.try_subtendril(5, 3).is_err());
+        assert!(t.try_subtendril(2, 6).is_ok());
+        assert_eq!(Some('a'), t.pop_front_char());
+        assert_eq!(Some('\0'), t.pop_front_char());
+        assert_eq!(Some('\u{1f4a9}'), t.pop_front_char());
+        assert_eq!(Some('\u{a66e}'), t.pop_front_char());
+        assert_eq!(None, t.pop_front_char());
+
+        let mut t = Tendril::<fmt::CESU8>::new();
+        assert!(t.try_push_char('\u{1f4a9}').is_ok());
+        assert_eq!(poo, t.as_byte_slice());
+        assert_eq!("\u{1f4a9}", &*t.to_utf8());
+        assert!(
+            b"plain ascii text"
+                .to_tendril()
+                .try_reinterpret::<fmt::ASCII>()
+                .unwrap()
+                .into_superset::<fmt::CESU8>()
+                .to_utf8()
+                == "plain ascii text".to_tendril()
+        );
+
+        let s = "a long string with \u{1f4a9} in it".to_tendril();
+        let c = s.to_cesu8();
+        assert_eq!(s.len() + 2, c.len32() as usize);
+        assert_eq!("a long string with \u{1f4a9} in it", &*c.to_utf8());
+
+        let s = "a long BMP string, \u{a66e}".to_tendril();
+        let c = s.to_cesu8();
+        assert!(s.is_shared_with(unsafe { c.reinterpret_view_without_validating() }));
+    }
+
+    #[test]
+    fn mutf8() {
+        fn mk(x: &[u8]) -> Tendril<fmt::MUTF8> {
+            x.to_tendril().try_reinterpret().unwrap()
+        }
+
+        assert!(b"a\x00"
+            .to_tendril()
+            .try_reinterpret::<fmt::MUTF8>()
+            .is_err());
+        assert!(b"\xC0"
+            .to_tendril()
+            .try_reinterpret::<fmt::MUTF8>()
+            .is_err());
+
+        let mut t = mk(b"a\xC0\x80\xED\xA0\xBD\xED\xB2\xA9");
+        assert!(t.try_subtendril(2, 1).is_err());
+        assert!(t.try_subtendril(1, 2).is_ok());
+        assert!(t.try_pop_back(7).is_err());
+        assert_eq!(Some('a'), t.pop_front_char());
+        assert_eq!(Some('\0'), t.pop_front_char());
+        assert_eq!(Some('\u{1f4a9}'), t.pop_front_char());
+        assert_eq!(None, t.pop_front_char());
+
+        let mut t = Tendril::<fmt::MUTF8>::new();
+        t.try_push_char('\0').unwrap();
+        t.try_push_char('x').unwrap();
+        assert_eq!(b"\xC0\x80x", t.as_byte_slice());
+        assert_eq!("\0x", &*t.to_utf8());
+
+        let s = "nul \0 and \u{1f4a9}".to_tendril();
+        let m = s.to_mutf8();
+        assert_eq!(
+            b"nul \xC0\x80 and \xED\xA0\xBD\xED\xB2\xA9",
+            m.as_byte_slice()
+        );
+        assert_eq!(s, m.to_utf8());
+    }
+
     #[test]
     fn format() {
         assert_eq!("", &*format_tendril!(""));
@@ -2362,7 +3248,7 @@ mod test {
 
     #[test]
     fn from_str() {
-        use std::str::FromStr;
+        use core::str::FromStr;
         let t: Tendril<_> = FromStr::from_str("foo bar baz").unwrap();
         assert_eq!("foo bar baz", &*t);
     }
@@ -2377,6 +3263,7 @@ mod test {
 
     #[test]
     #[cfg_attr(miri, ignore)] // slow
+    #[cfg(feature = "std")]
     fn read() {
         fn check(x: &[u8]) {
             use std::io::Cursor;
@@ -2392,6 +3279,7 @@ mod test {
         check(&long);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn hash_map_key() {
         use std::collections::HashMap;
@@ -2409,6 +3297,7 @@ mod test {
         assert_eq!(map.get(b"bar".as_ref()), None);
     }
 
+    #[cfg(all(feature = "std", feature = "atomic"))]
     #[test]
     fn atomic() {
         assert_send::<Tendril<fmt::UTF8, Atomic>>();
@@ -2430,6 +3319,7 @@ mod test {
         assert_eq!("this is a string", &*s);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn send() {
         assert_send::<SendTendril<fmt::UTF8>>();
@@ -2447,6 +3337,7 @@ mod test {
     }
 
     /// https://github.com/servo/tendril/issues/58
+    #[cfg(feature = "atomic")]
     #[test]
     fn issue_58() {
         let data = "<p><i>Hello!</p>, World!</i>";
@@ -2456,6 +3347,7 @@ mod test {
         assert_eq!(&*s, data);
     }
 
+    #[cfg(feature = "std")]
     #[test]
     fn inline_send() {
         let s = "x".to_tendril();
@@ -2470,4 +3362,55 @@ mod test {
         .unwrap();
         assert_eq!("x", &*t);
     }
+
+    #[test]
+    fn weak() {
+        let mut s = "a string on the heap".to_tendril();
+        let w = s.subtendril(2, 13).downgrade();
+        assert!(s.is_shared());
+        let w2 = w.clone();
+        assert_eq!("string on the", &*w.upgrade().unwrap());
+
+        // Mutating copies rather than writing into the buffer.
+        let t = s.clone();
+        s.push_slice(" and more");
+        assert_eq!("string on the", &*w2.upgrade().unwrap());
+
+        drop(t);
+        assert!(w.upgrade().is_none());
+        assert!(w2.upgrade().is_none());
+
+        let s = "inline".to_tendril();
+        let w = s.downgrade();
+        drop(s);
+        assert_eq!("inline", &*w.upgrade().unwrap());
+    }
+
+    #[test]
+    fn weak_keeps_strong_alive() {
+        let s = "a string on the heap".to_tendril();
+        let w = s.downgrade();
+        let t = w.upgrade().unwrap();
+        assert!(t.is_shared_with(&s));
+        drop(s);
+        assert_eq!("a string on the heap", &*w.upgrade().unwrap());
+        drop(t);
+        assert!(w.upgrade().is_none());
+    }
+
+    #[cfg(all(feature = "std", feature = "atomic"))]
+    #[test]
+    fn weak_atomic() {
+        assert_send::<WeakTendril<fmt::UTF8, Atomic>>();
+        let s: Tendril<fmt::UTF8, Atomic> = Tendril::from_slice("this is a string");
+        let w = s.downgrade();
+        let w = thread::spawn(move || {
+            assert_eq!("this is a string", &*w.upgrade().unwrap());
+            w
+        })
+        .join()
+        .unwrap();
+        drop(s);
+        assert!(w.upgrade().is_none());
+    }
 }
@@ -0,0 +1,250 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Conversion of tendrils between character formats.
+
+use alloc::vec::Vec;
+use core::fmt as strfmt;
+#[cfg(feature = "std")]
+use std::error::Error;
+
+use fmt::Transcodable;
+use tendril::{Atomicity, Tendril};
+
+/// A code point which can't be represented in the target format.
+#[derive(Copy, Clone, Hash, Debug, PartialEq, Eq)]
+pub struct TranscodeError {
+    /// Byte offset of the code point in the source tendril.
+    pub offset: usize,
+
+    /// The code point. This may be an unpaired surrogate.
+    pub code_point: u32,
+}
+
+impl strfmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        write!(
+            f,
+            "U+{:04X} at byte {} can't be represented in the target format",
+            self.code_point, self.offset
+        )
+    }
+}
+
+#[cfg(feature = "std")]
+impl Error for TranscodeError {}
+
+/// Conversion of a `Tendril` to another format, character by character.
+///
+/// This works between any two `Transcodable` formats, for example from
+/// `Latin1` to `UTF8` or from `WTF8` to `ASCII`. When the result has the
+/// same bytes as the source, as for all-ASCII `Latin1` converted to
+/// `UTF8`, the buffer is shared rather than copied.
+pub trait Transcode<A>
+where
+    A: Atomicity,
+{
+    /// Convert to the format `G`, failing at the first code point which `G`
+    /// can't represent.
+    fn transcode<G>(&self) -> Result<Tendril<G, A>, TranscodeError>
+    where
+        G: Transcodable;
+
+    /// Convert to the format `G`, writing `replacement` in place of each
+    /// code point which `G` can't represent.
+    ///
+    /// Panics if `G` can't represent `replacement` either.
+    fn transcode_lossy<G>(&self, replacement: char) -> Tendril<G, A>
+    where
+        G: Transcodable;
+}
+
+impl<F, A> Transcode<A> for Tendril<F, A>
+where
+    F: Transcodable,
+    A: Atomicity,
+{
+    #[inline]
+    fn transcode<G>(&self) -> Result<Tendril<G, A>, TranscodeError>
+    where
+        G: Transcodable,
+    {
+        run(self, None)
+    }
+
+    #[inline]
+    fn transcode_lossy<G>(&self, replacement: char) -> Tendril<G, A>
+    where
+        G: Transcodable,
+    {
+        if G::encode(replacement as u32, |_| ()).is_err() {
+            panic!("replacement character can't be represented in the target format");
+        }
+        run(self, Some(replacement)).unwrap()
+    }
+}
+
+/// Output of a transcoding, which stays a borrowed prefix of the input for
+/// as long as it matches.
+struct Output<'a> {
+    input: &'a [u8],
+    matched: usize,
+    copy: Option<Vec<u8>>,
+}
+
+impl<'a> Output<'a> {
+    #[inline]
+    fn push(&mut self, bytes: &[u8]) {
+        if let Some(ref mut copy) = self.copy {
+            copy.extend_from_slice(bytes);
+            return;
+        }
+        if self.input[self.matched..].starts_with(bytes) {
+            self.matched += bytes.len();
+        } else {
+            let mut copy = Vec::with_capacity(self.input.len());
+            copy.extend_from_slice(&self.input[..self.matched]);
+            copy.extend_from_slice(bytes);
+            self.copy = Some(copy);
+        }
+    }
+}
+
+fn run<F, G, A>(
+    t: &Tendril<F, A>,
+    replacement: Option<char>,
+) -> Result<Tendril<G, A>, TranscodeError>
+where
+    F: Transcodable,
+    G: Transcodable,
+    A: Atomicity,
+{
+    let input: &[u8] = t.as_bytes();
+    let mut out = Output {
+        input,
+        matched: 0,
+        copy: None,
+    };
+    let mut error = None;
+
+    unsafe {
+        F::decode(input, |offset, code_point| {
+            if G::encode(code_point, |b| out.push(b)).is_ok() {
+                return true;
+            }
+            match replacement {
+                Some(r) => {
+                    let _ = G::encode(r as u32, |b| out.push(b));
+                    true
+                }
+                None => {
+                    error = Some(TranscodeError { offset, code_point });
+                    false
+                }
+            }
+        });
+    }
+
+    if let Some(e) = error {
+        return Err(e);
+    }
+    unsafe {
+        Ok(match out.copy {
+            Some(copy) => Tendril::from_byte_slice_without_validating(&copy),
+            None if out.matched == input.len() => t.clone().reinterpret_without_validating(),
+            None => t
+                .as_bytes()
+                .subtendril(0, out.matched as u32)
+                .reinterpret_without_validating(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Transcode, TranscodeError};
+    use fmt;
+    use tendril::{SliceExt, Tendril};
+
+    fn latin1(x: &[u8]) -> Tendril<fmt::Latin1> {
+        x.to_tendril().try_reinterpret().unwrap()
+    }
+
+    #[test]
+    fn zero_copy() {
+        let t = latin1(b"plain ASCII in a Latin-1 tendril");
+        let u: Tendril<fmt::UTF8> = t.transcode().unwrap();
+        assert_eq!("plain ASCII in a Latin-1 tendril", &*u);
+        assert!(t.is_shared());
+        assert_eq!(t.as_bytes().as_ptr(), u.as_ptr());
+
+        let a: Tendril<fmt::ASCII> = u.transcode().unwrap();
+        assert_eq!(u.as_ptr(), a.as_bytes().as_ptr());
+    }
+
+    #[test]
+    fn copy() {
+        let t = latin1(b"caf\xe9 cr\xe8me br\xfbl\xe9e");
+        let u: Tendril<fmt::UTF8> = t.transcode().unwrap();
+        assert_eq!("café crème brûlée", &*u);
+        assert!(!t.is_shared());
+
+        let back: Tendril<fmt::Latin1> = u.transcode().unwrap();
+        assert!(back == t);
+
+        let u16: Tendril<fmt::UTF16LE> = u.transcode().unwrap();
+        assert_eq!(2 * 17, u16.len32());
+        let u8: Tendril<fmt::UTF8> = u16.transcode().unwrap();
+        assert_eq!(u, u8);
+    }
+
+    #[test]
+    fn unrepresentable() {
+        let u = "a \u{1f4a9} and \u{a66e}".to_tendril();
+        assert_eq!(
+            Err(TranscodeError {
+                offset: 2,
+                code_point: 0x1f4a9,
+            }),
+            u.transcode::<fmt::Latin1>().map(|_| ())
+        );
+        let t: Tendril<fmt::ASCII> = u.transcode_lossy('?');
+        assert_eq!(b"a ? and ?", &**t.as_bytes());
+    }
+
+    #[test]
+    #[should_panic]
+    fn unrepresentable_replacement() {
+        "\u{a66e}"
+            .to_tendril()
+            .transcode_lossy::<fmt::ASCII>('\u{FFFD}');
+    }
+
+    #[test]
+    fn wtf8() {
+        let w: Tendril<fmt::WTF8> = b"ab\xED\xA0\xBDcdefgh".to_tendril().try_reinterpret().unwrap();
+        assert_eq!(
+            Err(TranscodeError {
+                offset: 2,
+                code_point: 0xD83D,
+            }),
+            w.transcode::<fmt::UTF8>().map(|_| ())
+        );
+        assert_eq!("ab\u{FFFD}cdefgh", &*w.transcode_lossy::<fmt::UTF8>('\u{FFFD}'));
+        let same: Tendril<fmt::WTF8> = w.transcode().unwrap();
+        assert!(same.is_shared_with(&w));
+
+        let u: Tendril<fmt::WTF8> = "\u{1f4a9}".to_tendril().transcode().unwrap();
+        assert_eq!(b"\xF0\x9F\x92\xA9", &**u.as_bytes());
+    }
+
+    #[test]
+    fn prefix_match() {
+        let t: Tendril<fmt::UTF16LE> = "ab".to_tendril().transcode().unwrap();
+        let u: Tendril<fmt::ASCII> = t.subtendril(0, 2).transcode().unwrap();
+        assert_eq!(b"a", &**u.as_bytes());
+    }
+}
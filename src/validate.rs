@@ -0,0 +1,229 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fast validation for mostly-ASCII input.
+//!
+//! Runs of ASCII are skipped many bytes at a time: with AVX2 or SSE2 on
+//! x86-64, and a word at a time elsewhere. The rest is left to the usual
+//! validators. AVX2 is detected at run time when `std` is available.
+
+use core::{cmp, mem, ptr, str};
+
+/// Get the length of the ASCII prefix of `buf`.
+#[inline]
+pub fn ascii_prefix_len(buf: &[u8]) -> usize {
+    if buf.len() < 16 {
+        return ascii_prefix_len_words(buf);
+    }
+    imp::ascii_prefix_len(buf)
+}
+
+/// Is `buf` entirely ASCII?
+#[inline]
+pub fn is_ascii(buf: &[u8]) -> bool {
+    ascii_prefix_len(buf) == buf.len()
+}
+
+/// Get the length of the ASCII run at the start of `buf`, which begins
+/// with an ASCII byte. Lone ASCII bytes between other characters are
+/// common, and not worth a trip through `ascii_prefix_len`.
+#[inline]
+pub fn ascii_run_len(buf: &[u8]) -> usize {
+    match buf.get(1) {
+        Some(&b) if b < 0x80 => 2 + ascii_prefix_len(&buf[2..]),
+        _ => 1,
+    }
+}
+
+/// Is `buf` valid UTF-8?
+///
+/// Runs of ASCII are skipped, and the rest goes to `str::from_utf8` a
+/// window at a time. Each window ends at a character boundary, if the
+/// input is valid, so the windows are valid exactly when all of it is.
+pub fn is_utf8(buf: &[u8]) -> bool {
+    const WINDOW: usize = 256;
+    let mut i = 0;
+    while i < buf.len() {
+        i += ascii_prefix_len(&buf[i..]);
+        if i == buf.len() {
+            break;
+        }
+        let mut end = cmp::min(i + WINDOW, buf.len());
+        // Back up over at most three continuation bytes. With more, the
+        // next window will start with one and fail.
+        for _ in 0..3 {
+            if end < buf.len() && buf[end] & 0xC0 == 0x80 {
+                end -= 1;
+            }
+        }
+        if str::from_utf8(&buf[i..end]).is_err() {
+            return false;
+        }
+        i = end;
+    }
+    true
+}
+
+const WORD: usize = mem::size_of::<usize>();
+
+/// The high bit of every byte in a word.
+const HIGH_BITS: usize = !0 / 0xFF * 0x80;
+
+#[inline]
+fn ascii_prefix_len_words(buf: &[u8]) -> usize {
+    let mut i = 0;
+    while i + WORD <= buf.len() {
+        let word = unsafe { ptr::read_unaligned(buf.as_ptr().add(i) as *const usize) };
+        if word & HIGH_BITS != 0 {
+            break;
+        }
+        i += WORD;
+    }
+    while i < buf.len() && buf[i] < 0x80 {
+        i += 1;
+    }
+    i
+}
+
+#[cfg(target_arch = "x86_64")]
+mod imp {
+    use core::arch::x86_64::*;
+
+    #[inline]
+    pub fn ascii_prefix_len(buf: &[u8]) -> usize {
+        // SSE2 is always there on x86-64.
+        unsafe {
+            if buf.len() >= 64 && has_avx2() {
+                avx2(buf)
+            } else {
+                sse2(buf)
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[inline]
+    fn has_avx2() -> bool {
+        is_x86_feature_detected!("avx2")
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[inline]
+    fn has_avx2() -> bool {
+        cfg!(target_feature = "avx2")
+    }
+
+    #[target_feature(enable = "avx2")]
+    unsafe fn avx2(buf: &[u8]) -> usize {
+        let p = buf.as_ptr();
+        let mut i = 0;
+        while i + 64 <= buf.len() {
+            let a = _mm256_loadu_si256(p.add(i) as *const __m256i);
+            let b = _mm256_loadu_si256(p.add(i + 32) as *const __m256i);
+            if _mm256_movemask_epi8(_mm256_or_si256(a, b)) != 0 {
+                break;
+            }
+            i += 64;
+        }
+        while i + 32 <= buf.len() {
+            let a = _mm256_loadu_si256(p.add(i) as *const __m256i);
+            let mask = _mm256_movemask_epi8(a);
+            if mask != 0 {
+                return i + mask.trailing_zeros() as usize;
+            }
+            i += 32;
+        }
+        i + sse2(&buf[i..])
+    }
+
+    #[target_feature(enable = "sse2")]
+    unsafe fn sse2(buf: &[u8]) -> usize {
+        let p = buf.as_ptr();
+        let mut i = 0;
+        while i + 16 <= buf.len() {
+            let a = _mm_loadu_si128(p.add(i) as *const __m128i);
+            let mask = _mm_movemask_epi8(a);
+            if mask != 0 {
+                return i + mask.trailing_zeros() as usize;
+            }
+            i += 16;
+        }
+        i + super::ascii_prefix_len_words(&buf[i..])
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+mod imp {
+    #[inline]
+    pub fn ascii_prefix_len(buf: &[u8]) -> usize {
+        super::ascii_prefix_len_words(buf)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ascii_prefix_len, ascii_prefix_len_words, is_ascii, is_utf8};
+    use alloc::vec;
+    use core::str;
+    use rand::{self, Rng};
+
+    #[test]
+    fn ascii() {
+        let mut buf = vec![b'a'; 300];
+        for len in 0..buf.len() {
+            assert_eq!(len, ascii_prefix_len(&buf[..len]));
+            assert!(is_ascii(&buf[..len]));
+        }
+        for pos in 0..buf.len() {
+            buf[pos] = 0xC3;
+            for start in 0..8 {
+                let expected = if pos >= start { pos } else { buf.len() } - start;
+                assert_eq!(expected, ascii_prefix_len(&buf[start..]));
+                assert_eq!(expected, ascii_prefix_len_words(&buf[start..]));
+            }
+            assert!(!is_ascii(&buf));
+            buf[pos] = b'a';
+        }
+    }
+
+    #[test]
+    fn utf8_short_sequences() {
+        for a in 0..=0xFF_u8 {
+            for b in 0..=0xFF_u8 {
+                let two = [a, b];
+                assert_eq!(str::from_utf8(&two).is_ok(), is_utf8(&two), "{:?}", two);
+                for &c in &[0x41, 0x80, 0xBF, 0xC0] {
+                    let three = [a, b, c];
+                    assert_eq!(str::from_utf8(&three).is_ok(), is_utf8(&three));
+                    let four = [a, b, c, 0x80];
+                    assert_eq!(str::from_utf8(&four).is_ok(), is_utf8(&four));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn utf8_random() {
+        let mut rng = rand::thread_rng();
+        let pieces: &[&[u8]] = &[
+            b"plain ASCII text ",
+            b"caf\xc3\xa9",
+            b"\xe2\x82\xac",
+            b"\xf0\x9f\x92\xa9",
+            b"\xff",
+            b"\xed\xa0\x80",
+            b"\xe2\x82",
+            b"\x80",
+        ];
+        for _ in 0..2000 {
+            let mut buf = vec![];
+            for _ in 0..rng.gen_range(0, 12) {
+                buf.extend_from_slice(rng.choose(pieces).unwrap());
+            }
+            assert_eq!(str::from_utf8(&buf).is_ok(), is_utf8(&buf), "{:?}", buf);
+        }
+    }
+}
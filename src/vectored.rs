@@ -0,0 +1,353 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Writing many tendrils at once with vectored I/O.
+
+use std::borrow::Cow;
+use std::io::{self, IoSlice};
+use std::marker::PhantomData;
+
+use fmt;
+use stream::TendrilSink;
+use tendril::{Atomicity, NonAtomic, Tendril};
+
+/// The most slices passed to one `write_vectored` call. This is the usual
+/// `IOV_MAX`.
+const MAX_SLICES: usize = 1024;
+
+/// Write every byte of a sequence of tendrils, using `write_vectored` so
+/// that they needn't be copied into one buffer first.
+pub fn write_all_vectored<W, F, A>(w: &mut W, tendrils: &[Tendril<F, A>]) -> io::Result<()>
+where
+    W: io::Write + ?Sized,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    let mut slices = Vec::with_capacity(tendrils.len().min(MAX_SLICES));
+    let (mut i, mut offset) = (0, 0);
+    while i < tendrils.len() {
+        slices.clear();
+        let rest = tendrils[i..].iter().map(|t| t.as_bytes());
+        for (j, bytes) in rest.take(MAX_SLICES).enumerate() {
+            let start = if j == 0 { offset } else { 0 };
+            if bytes.len() > start {
+                slices.push(IoSlice::new(&bytes[start..]));
+            }
+        }
+        if slices.is_empty() {
+            // Nothing but empty tendrils in this window.
+            i += MAX_SLICES;
+            offset = 0;
+            continue;
+        }
+
+        let mut n = match w.write_vectored(&slices) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    "failed to write whole buffer",
+                ))
+            }
+            Ok(n) => n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        // Skip past what was written.
+        while n > 0 {
+            let left = tendrils[i].len32() as usize - offset;
+            if n < left {
+                offset += n;
+                break;
+            }
+            n -= left;
+            i += 1;
+            offset = 0;
+        }
+        while i < tendrils.len() && offset == tendrils[i].len32() as usize {
+            i += 1;
+            offset = 0;
+        }
+    }
+    Ok(())
+}
+
+/// A `TendrilSink` which holds on to tendrils, without copying them, and
+/// writes them out together with `write_vectored`.
+///
+/// Tendrils are written once the pending ones add up to `max_bytes`, or
+/// number `max_tendrils`. The first I/O error stops all writing, and is
+/// returned by the next `flush` or `finish`. Errors reported by the sender
+/// are ignored.
+///
+/// Dropping the writer writes out the pending tendrils, but any error is
+/// lost. Call `finish` to see it.
+pub struct VectoredWriter<W, F, A = NonAtomic>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    // Only `None` once `finish` has taken it.
+    inner: Option<W>,
+    pending: Vec<Tendril<fmt::Bytes, A>>,
+    pending_bytes: usize,
+    max_bytes: usize,
+    max_tendrils: usize,
+    failed: bool,
+    error: Option<io::Error>,
+    marker: PhantomData<F>,
+}
+
+impl<W, F, A> VectoredWriter<W, F, A>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    /// Create a writer which flushes at 64 KiB or 256 tendrils.
+    #[inline]
+    pub fn new(inner: W) -> VectoredWriter<W, F, A> {
+        VectoredWriter::with_thresholds(inner, 64 * 1024, 256)
+    }
+
+    /// Create a writer with the given flush thresholds.
+    #[inline]
+    pub fn with_thresholds(
+        inner: W,
+        max_bytes: usize,
+        max_tendrils: usize,
+    ) -> VectoredWriter<W, F, A> {
+        VectoredWriter {
+            inner: Some(inner),
+            pending: Vec::new(),
+            pending_bytes: 0,
+            max_bytes,
+            max_tendrils,
+            failed: false,
+            error: None,
+            marker: PhantomData,
+        }
+    }
+
+    /// Get a reference to the underlying writer.
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    /// Write out every pending tendril.
+    ///
+    /// Once a write has failed, this returns its error, and after that an
+    /// error of kind `Other`.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.write_pending();
+        match self.error.take() {
+            Some(e) => Err(e),
+            None if self.failed => Err(io::ErrorKind::Other.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Write out every pending tendril, keeping any error for `flush`.
+    fn write_pending(&mut self) {
+        if !self.failed && !self.pending.is_empty() {
+            let inner = self.inner.as_mut().unwrap();
+            if let Err(e) = write_all_vectored(inner, &self.pending) {
+                self.failed = true;
+                self.error = Some(e);
+            }
+        }
+        self.pending.clear();
+        self.pending_bytes = 0;
+    }
+}
+
+impl<W, F, A> TendrilSink<F, A> for VectoredWriter<W, F, A>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    #[inline]
+    fn process(&mut self, t: Tendril<F, A>) {
+        if t.len32() == 0 {
+            return;
+        }
+        self.pending_bytes += t.len32() as usize;
+        self.pending.push(t.into_bytes());
+        if self.pending_bytes >= self.max_bytes || self.pending.len() >= self.max_tendrils {
+            self.write_pending();
+        }
+    }
+
+    #[inline]
+    fn error(&mut self, _desc: Cow<'static, str>) {}
+
+    type Output = io::Result<W>;
+
+    #[inline]
+    fn finish(mut self) -> io::Result<W> {
+        self.flush()?;
+        let mut inner = self.inner.take().unwrap();
+        inner.flush()?;
+        Ok(inner)
+    }
+}
+
+impl<W, F, A> Drop for VectoredWriter<W, F, A>
+where
+    W: io::Write,
+    F: fmt::Format,
+    A: Atomicity,
+{
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.flush();
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{write_all_vectored, VectoredWriter};
+    use std::io::{self, IoSlice};
+    use stream::TendrilSink;
+    use tendril::{ByteTendril, SliceExt, StrTendril};
+
+    /// Takes at most `limit` bytes per call, and counts calls.
+    struct Trickle {
+        out: Vec<u8>,
+        limit: usize,
+        calls: usize,
+    }
+
+    impl io::Write for Trickle {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.write_vectored(&[IoSlice::new(buf)])
+        }
+
+        fn write_vectored(&mut self, bufs: &[IoSlice]) -> io::Result<usize> {
+            self.calls += 1;
+            let mut n = 0;
+            for buf in bufs {
+                let take = (self.limit - n).min(buf.len());
+                self.out.extend_from_slice(&buf[..take]);
+                n += take;
+            }
+            Ok(n)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn trickle(limit: usize) -> Trickle {
+        Trickle {
+            out: vec![],
+            limit,
+            calls: 0,
+        }
+    }
+
+    #[test]
+    fn write_all() {
+        let ts: Vec<ByteTendril> = vec![
+            b"HTTP/1.1 200 OK\r\n".to_tendril(),
+            ByteTendril::new(),
+            b"Content-Length: 5\r\n\r\n".to_tendril(),
+            b"hello".to_tendril(),
+        ];
+        let mut w = trickle(1 << 20);
+        write_all_vectored(&mut w, &ts).unwrap();
+        assert_eq!(1, w.calls);
+        assert_eq!(
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..],
+            &*w.out
+        );
+
+        let mut w = trickle(7);
+        write_all_vectored(&mut w, &ts).unwrap();
+        assert_eq!(7, w.calls);
+        assert_eq!(
+            &b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello"[..],
+            &*w.out
+        );
+
+        let mut w = trickle(0);
+        let err = write_all_vectored(&mut w, &ts).unwrap_err();
+        assert_eq!(io::ErrorKind::WriteZero, err.kind());
+        write_all_vectored(&mut w, &[] as &[StrTendril]).unwrap();
+    }
+
+    #[test]
+    fn sink() {
+        let mut sink = VectoredWriter::with_thresholds(trickle(1000), 20, 3);
+        sink.process("one, ".to_tendril());
+        sink.process("two, ".to_tendril());
+        assert_eq!(0, sink.get_ref().calls);
+        sink.process("three, ".to_tendril());
+        assert_eq!(1, sink.get_ref().calls);
+        sink.process("and a long one to pass the byte threshold".to_tendril());
+        assert_eq!(2, sink.get_ref().calls);
+        sink.process("end".to_tendril());
+        let w = sink.finish().unwrap();
+        assert_eq!(3, w.calls);
+        assert_eq!(
+            &b"one, two, three, and a long one to pass the byte thresholdend"[..],
+            &*w.out
+        );
+    }
+
+    #[test]
+    fn sink_drop() {
+        let mut out = vec![];
+        {
+            let mut sink = VectoredWriter::new(&mut out);
+            sink.process("written on drop".to_tendril());
+        }
+        assert_eq!(b"written on drop", &*out);
+
+        let mut sink = VectoredWriter::new(Broken);
+        sink.process(b"lost".to_tendril());
+        drop(sink);
+    }
+
+    #[test]
+    fn sink_error() {
+        let mut sink = VectoredWriter::with_thresholds(trickle(0), 1, 1);
+        sink.process(b"lost".to_tendril());
+        sink.process(b"also lost".to_tendril());
+        assert_eq!(1, sink.get_ref().calls);
+        let err = sink.finish().err().unwrap();
+        assert_eq!(io::ErrorKind::WriteZero, err.kind());
+    }
+
+    struct Broken;
+
+    impl io::Write for Broken {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::Error::from_raw_os_error(32))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn sink_error_kept() {
+        let mut sink = VectoredWriter::with_thresholds(Broken, 1, 1);
+        sink.process(b"lost".to_tendril());
+        assert_eq!(Some(32), sink.flush().unwrap_err().raw_os_error());
+        assert_eq!(None, sink.flush().unwrap_err().raw_os_error());
+
+        let mut sink = VectoredWriter::new(Broken);
+        sink.process(b"lost".to_tendril());
+        assert_eq!(Some(32), sink.finish().err().unwrap().raw_os_error());
+    }
+}
@@ -0,0 +1,291 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Code point access for WTF-8 tendrils.
+
+use alloc::vec::Vec;
+use core::char;
+use core::fmt as strfmt;
+
+use fmt::{self, Transcodable};
+use futf::{self, Meaning};
+use tendril::{Atomicity, Tendril};
+use transcode::{Transcode, TranscodeError};
+
+/// A Unicode code point: a `char`, or an unpaired surrogate.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CodePoint(u32);
+
+impl CodePoint {
+    /// Make a code point from its value, if it is at most `U+10FFFF`.
+    #[inline]
+    pub fn from_u32(n: u32) -> Option<CodePoint> {
+        match n {
+            0..=0x10FFFF => Some(CodePoint(n)),
+            _ => None,
+        }
+    }
+
+    /// Make a code point from a character.
+    #[inline]
+    pub fn from_char(c: char) -> CodePoint {
+        CodePoint(c as u32)
+    }
+
+    /// Get the value of the code point.
+    #[inline]
+    pub fn to_u32(self) -> u32 {
+        self.0
+    }
+
+    /// Get the character, or `None` for a surrogate.
+    #[inline]
+    pub fn to_char(self) -> Option<char> {
+        char::from_u32(self.0)
+    }
+
+    /// Get the character, or `U+FFFD` for a surrogate.
+    #[inline]
+    pub fn to_char_lossy(self) -> char {
+        self.to_char().unwrap_or('\u{FFFD}')
+    }
+
+    /// Is this a surrogate?
+    #[inline]
+    pub fn is_surrogate(self) -> bool {
+        (0xD800..=0xDFFF).contains(&self.0)
+    }
+}
+
+impl From<char> for CodePoint {
+    #[inline]
+    fn from(c: char) -> CodePoint {
+        CodePoint::from_char(c)
+    }
+}
+
+impl strfmt::Debug for CodePoint {
+    fn fmt(&self, f: &mut strfmt::Formatter) -> strfmt::Result {
+        write!(f, "U+{:04X}", self.0)
+    }
+}
+
+/// Iterator over the code points of a WTF-8 tendril.
+///
+/// Made by `Tendril::code_points`.
+pub struct CodePoints<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Iterator for CodePoints<'a> {
+    type Item = CodePoint;
+
+    #[inline]
+    fn next(&mut self) -> Option<CodePoint> {
+        let (cp, len) = classify_front(&self.buf[self.pos..])?;
+        self.pos += len;
+        Some(cp)
+    }
+}
+
+/// Decode the code point at the start of a valid WTF-8 buffer, and give its
+/// length in bytes.
+#[inline]
+fn classify_front(buf: &[u8]) -> Option<(CodePoint, usize)> {
+    let codept = futf::classify(buf, 0)?;
+    let n = match codept.meaning {
+        Meaning::Whole(c) => c as u32,
+        Meaning::LeadSurrogate(n) => 0xD800 + n as u32,
+        Meaning::TrailSurrogate(n) => 0xDC00 + n as u32,
+        _ => panic!("WTF8: internal error"),
+    };
+    Some((CodePoint(n), codept.bytes.len()))
+}
+
+impl<A> Tendril<fmt::WTF8, A>
+where
+    A: Atomicity,
+{
+    /// Iterate over the code points, including any unpaired surrogates.
+    #[inline]
+    pub fn code_points<'a>(&'a self) -> CodePoints<'a> {
+        CodePoints {
+            buf: self.as_bytes(),
+            pos: 0,
+        }
+    }
+
+    /// Remove and return the first code point, if any.
+    #[inline]
+    pub fn pop_front_code_point(&mut self) -> Option<CodePoint> {
+        let (cp, len) = classify_front(self.as_bytes())?;
+        if len as u32 == self.len32() {
+            self.clear();
+        } else {
+            unsafe {
+                self.unsafe_pop_front(len as u32);
+            }
+        }
+        Some(cp)
+    }
+
+    /// Push a code point onto the end.
+    ///
+    /// A trail surrogate pushed after a lead surrogate joins up with it
+    /// into one supplementary character.
+    #[inline]
+    pub fn push_code_point(&mut self, cp: CodePoint) {
+        let _ = fmt::WTF8::encode(cp.0, |b| unsafe {
+            self.push_bytes_without_validating(b);
+        });
+    }
+
+    /// Build a `Tendril` from potentially ill-formed UTF-16, as used by
+    /// Windows for `OsStr`. Unpaired surrogates are kept.
+    #[inline]
+    pub fn from_wide(units: &[u16]) -> Tendril<fmt::WTF8, A> {
+        let mut bytes = Vec::with_capacity(units.len());
+        for r in char::decode_utf16(units.iter().cloned()) {
+            let n = match r {
+                Ok(c) => c as u32,
+                Err(e) => e.unpaired_surrogate() as u32,
+            };
+            let _ = fmt::WTF8::encode(n, |b| bytes.extend_from_slice(b));
+        }
+        unsafe { Tendril::from_byte_slice_without_validating(&bytes) }
+    }
+
+    /// Convert to potentially ill-formed UTF-16, as used by Windows for
+    /// `OsStr`.
+    #[inline]
+    pub fn to_wide(&self) -> Vec<u16> {
+        let mut units = Vec::with_capacity(self.len32() as usize);
+        for cp in self.code_points() {
+            match cp.0 {
+                n @ 0..=0xFFFF => units.push(n as u16),
+                n => {
+                    let n = n - 0x10000;
+                    units.push(0xD800 | (n >> 10) as u16);
+                    units.push(0xDC00 | (n & 0x3FF) as u16);
+                }
+            }
+        }
+        units
+    }
+
+    /// Convert to UTF-8, replacing each unpaired surrogate with `U+FFFD`.
+    ///
+    /// When there are no unpaired surrogates, the buffer is shared rather
+    /// than copied.
+    #[inline]
+    pub fn to_utf8_lossy(&self) -> Tendril<fmt::UTF8, A> {
+        self.transcode_lossy('\u{FFFD}')
+    }
+
+    /// Convert to UTF-8 for free, if there are no unpaired surrogates.
+    ///
+    /// This is `try_into_subset::<fmt::UTF8>`, but on failure it also
+    /// reports the position of the first unpaired surrogate.
+    #[inline]
+    pub fn try_into_utf8(self) -> Result<Tendril<fmt::UTF8, A>, (Self, TranscodeError)> {
+        let err = {
+            let mut iter = self.code_points();
+            loop {
+                let offset = iter.pos;
+                match iter.next() {
+                    None => break None,
+                    Some(cp) if cp.is_surrogate() => {
+                        break Some(TranscodeError {
+                            offset,
+                            code_point: cp.0,
+                        })
+                    }
+                    Some(_) => {}
+                }
+            }
+        };
+        if let Some(err) = err {
+            return Err((self, err));
+        }
+        Ok(unsafe { self.reinterpret_without_validating() })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CodePoint;
+    use alloc::{string::String, vec, vec::Vec};
+    use fmt;
+    use tendril::{SliceExt, Tendril};
+    use transcode::TranscodeError;
+
+    fn wtf8(x: &[u8]) -> Tendril<fmt::WTF8> {
+        x.to_tendril().try_reinterpret().unwrap()
+    }
+
+    #[test]
+    fn code_points() {
+        let t = wtf8(b"a\xED\xA0\xBD\xF0\x9F\x92\xA9\xED\xB2\xA9");
+        let cps: Vec<u32> = t.code_points().map(CodePoint::to_u32).collect();
+        assert_eq!(vec![0x61, 0xD83D, 0x1F4A9, 0xDCA9], cps);
+        let chars: String = t.code_points().map(CodePoint::to_char_lossy).collect();
+        assert_eq!("a\u{FFFD}\u{1f4a9}\u{FFFD}", chars);
+        assert!(CodePoint::from_u32(0x110000).is_none());
+        assert_eq!(Some('x'), CodePoint::from('x').to_char());
+    }
+
+    #[test]
+    fn pop_and_push() {
+        let mut t = wtf8(b"a\xED\xA0\xBDb");
+        assert_eq!(0x61, t.pop_front_code_point().unwrap().to_u32());
+        assert!(t.pop_front_code_point().unwrap().is_surrogate());
+        assert_eq!(Some('b'), t.pop_front_code_point().unwrap().to_char());
+        assert!(t.pop_front_code_point().is_none());
+
+        let mut t = Tendril::<fmt::WTF8>::new();
+        t.push_code_point(CodePoint::from('x'));
+        t.push_code_point(CodePoint::from_u32(0xD83D).unwrap());
+        assert_eq!(b"x\xED\xA0\xBD", &**t.as_bytes());
+        t.push_code_point(CodePoint::from_u32(0xDCA9).unwrap());
+        assert_eq!(b"x\xF0\x9F\x92\xA9", &**t.as_bytes());
+    }
+
+    #[test]
+    fn wide() {
+        let units = [0x61, 0xD83D, 0xDCA9, 0xDCA9, 0xD83D];
+        let t = Tendril::<fmt::WTF8>::from_wide(&units);
+        assert_eq!(
+            b"a\xF0\x9F\x92\xA9\xED\xB2\xA9\xED\xA0\xBD",
+            &**t.as_bytes()
+        );
+        assert_eq!(&units[..], &*t.to_wide());
+    }
+
+    #[test]
+    fn to_utf8() {
+        let t = wtf8(b"no surrogates in this one");
+        let u = t.to_utf8_lossy();
+        assert_eq!("no surrogates in this one", &*u);
+        assert_eq!(t.as_bytes().as_ptr(), u.as_ptr());
+        assert_eq!(
+            "no surrogates in this one",
+            &*t.try_into_utf8().ok().unwrap()
+        );
+
+        let t = wtf8(b"\xC3\xA9t\xC3\xA9 \xED\xB2\xA9!");
+        assert_eq!("été \u{FFFD}!", &*t.to_utf8_lossy());
+        let (t, err) = t.try_into_utf8().unwrap_err();
+        assert_eq!(
+            TranscodeError {
+                offset: 6,
+                code_point: 0xDCA9,
+            },
+            err
+        );
+        assert_eq!(10, t.len32());
+    }
+}